@@ -0,0 +1,38 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use smart_dns::core_dns::{BytePacketBuffer, DnsName, DnsPacket, MAX_PACKET_SIZE};
+
+// 任意字节都不能让解析器 panic; 能解析的报文写出后必须解析回同样的内容 (域名区分大小写),
+// 其中的域名经过文本形式(带转义)后也必须不变
+fuzz_target!(|data: &[u8]| {
+    let mut packet = match DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(data)) {
+        Ok(packet) => packet,
+        Err(_) => return,
+    };
+
+    for question in &packet.questions {
+        let text = question.name.to_string();
+        assert!(text.parse::<DnsName>().unwrap().eq_exact(&question.name));
+    }
+
+    let mut buffer = BytePacketBuffer::growable(MAX_PACKET_SIZE);
+    if packet.write(&mut buffer).is_ok() {
+        let parsed = DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&buffer.buf[..buffer.pos])).unwrap();
+        assert_eq!(parsed.questions.len(), packet.questions.len());
+        for (a, b) in parsed.questions.iter().zip(&packet.questions) {
+            assert_eq!(a, b);
+            assert!(a.name.eq_exact(&b.name));
+        }
+        for (parsed, records) in [
+            (&parsed.answers, &packet.answers),
+            (&parsed.authorities, &packet.authorities),
+            (&parsed.resources, &packet.resources),
+        ] {
+            assert_eq!(parsed.len(), records.len());
+            for (a, b) in parsed.iter().zip(records) {
+                assert!(a.eq_exact(b), "{} != {}", a, b);
+            }
+        }
+    }
+});
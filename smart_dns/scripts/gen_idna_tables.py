@@ -0,0 +1,136 @@
+#!/usr/bin/env python3
+# 生成 src/idna/tables.rs: IDNA2008 码点属性 (RFC 5892)、宽度映射 (RFC 5895) 和 NFC 规范化所需的数据表。
+# 数据来自 Python 自带的 unicodedata 模块, 表中的 Unicode 版本与运行的 Python 一致。
+#
+#     python3 scripts/gen_idna_tables.py > src/idna/tables.rs
+
+import unicodedata as ud
+
+# RFC 5892 2.6 Exceptions
+EXCEPTIONS_PVALID = {0x00DF, 0x03C2, 0x06FD, 0x06FE, 0x0F0B, 0x3007}
+EXCEPTIONS_CONTEXTO = {0x00B7, 0x0375, 0x05F3, 0x05F4, 0x30FB, *range(0x0660, 0x066A), *range(0x06F0, 0x06FA)}
+EXCEPTIONS_DISALLOWED = {0x0640, 0x07FA, 0x302E, 0x302F, *range(0x3031, 0x3036), 0x303B}
+
+# DerivedCoreProperties.txt: Default_Ignorable_Code_Point
+DEFAULT_IGNORABLE = [
+    (0x00AD, 0x00AD), (0x034F, 0x034F), (0x061C, 0x061C), (0x115F, 0x1160), (0x17B4, 0x17B5),
+    (0x180B, 0x180F), (0x200B, 0x200F), (0x202A, 0x202E), (0x2060, 0x206F), (0x3164, 0x3164),
+    (0xFE00, 0xFE0F), (0xFEFF, 0xFEFF), (0xFFA0, 0xFFA0), (0xFFF0, 0xFFF8), (0x1BCA0, 0x1BCA3),
+    (0x1D173, 0x1D17A), (0xE0000, 0xE0FFF),
+]
+# PropList.txt: White_Space
+WHITE_SPACE = [
+    (0x0009, 0x000D), (0x0020, 0x0020), (0x0085, 0x0085), (0x00A0, 0x00A0), (0x1680, 0x1680),
+    (0x2000, 0x200A), (0x2028, 0x2029), (0x202F, 0x202F), (0x205F, 0x205F), (0x3000, 0x3000),
+]
+# RFC 5892 2.4 IgnorableBlocks
+IGNORABLE_BLOCKS = [(0x20D0, 0x20FF), (0x1D100, 0x1D1FF), (0x1D200, 0x1D24F)]
+# HangulSyllableType.txt: L, V, T
+OLD_HANGUL_JAMO = [(0x1100, 0x11FF), (0xA960, 0xA97C), (0xD7B0, 0xD7C6), (0xD7CB, 0xD7FB)]
+LETTER_DIGITS = {"Ll", "Lu", "Lo", "Nd", "Lm", "Mn", "Mc"}
+
+
+def within(cp, ranges):
+    return any(lo <= cp <= hi for lo, hi in ranges)
+
+
+def noncharacter(cp):
+    return 0xFDD0 <= cp <= 0xFDEF or (cp & 0xFFFE) == 0xFFFE
+
+
+# RFC 5892 3: 只区分 PVALID 和其他; CONTEXTJ/CONTEXTO 在代码中按规则处理
+def pvalid(cp):
+    c = chr(cp)
+    if cp in EXCEPTIONS_PVALID:
+        return True
+    if cp in EXCEPTIONS_CONTEXTO or cp in EXCEPTIONS_DISALLOWED:
+        return False
+    if 0xD800 <= cp <= 0xDFFF:
+        return False
+    category = ud.category(c)
+    if category == "Cn" and not noncharacter(cp):
+        return False  # UNASSIGNED
+    if c in "abcdefghijklmnopqrstuvwxyz0123456789-":
+        return True
+    if cp in (0x200C, 0x200D):
+        return False  # CONTEXTJ
+    if ud.normalize("NFKC", ud.normalize("NFKC", c).casefold()) != c:
+        return False  # Unstable
+    if within(cp, DEFAULT_IGNORABLE) or within(cp, WHITE_SPACE) or noncharacter(cp):
+        return False
+    if within(cp, IGNORABLE_BLOCKS) or within(cp, OLD_HANGUL_JAMO):
+        return False
+    return category in LETTER_DIGITS
+
+
+def ranges(predicate):
+    out = []
+    start = None
+    for cp in range(0x110001):
+        inside = cp <= 0x10FFFF and predicate(cp)
+        if inside and start is None:
+            start = cp
+        elif not inside and start is not None:
+            out.append((start, cp - 1))
+            start = None
+    return out
+
+
+def emit(name, kind, rows, per_line):
+    print("pub const %s: &[%s] = &[" % (name, kind))
+    for i in range(0, len(rows), per_line):
+        print("    " + " ".join(rows[i:i + per_line]))
+    print("];")
+    print()
+
+
+def main():
+    print("// 由 scripts/gen_idna_tables.py 生成, 不要手工修改。")
+    print("// Unicode %s" % ud.unidata_version)
+    print()
+
+    emit("PVALID", "(u32, u32)", ["(0x%X, 0x%X)," % r for r in ranges(pvalid)], 6)
+
+    # 标签不能以组合标记开头 (RFC 5891 4.2.3.2)
+    marks = ranges(lambda cp: ud.category(chr(cp)) in ("Mn", "Mc", "Me"))
+    emit("MARK", "(u32, u32)", ["(0x%X, 0x%X)," % r for r in marks], 6)
+
+    # 全角/半角字符映射成它们的 <wide>/<narrow> 分解 (RFC 5895 2 第2步), 都是单个字符
+    widths = []
+    for cp in range(0x110000):
+        mapping = ud.decomposition(chr(cp))
+        if mapping.startswith("<wide>") or mapping.startswith("<narrow>"):
+            widths.append((cp, int(mapping.split()[1], 16)))
+    emit("WIDTH", "(u32, u32)", ["(0x%X, 0x%X)," % r for r in widths], 6)
+
+    ccc = []
+    for cp in range(0x110000):
+        value = ud.combining(chr(cp))
+        if value == 0:
+            continue
+        if ccc and ccc[-1][1] == cp - 1 and ccc[-1][2] == value:
+            ccc[-1][1] = cp
+        else:
+            ccc.append([cp, cp, value])
+    emit("COMBINING_CLASS", "(u32, u32, u8)", ["(0x%X, 0x%X, %d)," % tuple(r) for r in ccc], 5)
+
+    # 规范分解 (不含用算法处理的韩文音节), 单个字符的分解第二项为 0
+    decompositions = []
+    compositions = []
+    for cp in range(0x110000):
+        if 0xAC00 <= cp <= 0xD7A3:
+            continue
+        mapping = ud.decomposition(chr(cp))
+        if not mapping or mapping.startswith("<"):
+            continue
+        parts = [int(p, 16) for p in mapping.split()]
+        decompositions.append((cp, parts[0], parts[1] if len(parts) > 1 else 0))
+        # 不在完全组合排除集中的二元分解才参与组合
+        if len(parts) == 2 and ud.normalize("NFC", chr(cp)) == chr(cp):
+            compositions.append((parts[0], parts[1], cp))
+    emit("DECOMPOSITION", "(u32, u32, u32)", ["(0x%X, 0x%X, 0x%X)," % r for r in decompositions], 4)
+    compositions.sort()
+    emit("COMPOSITION", "(u32, u32, u32)", ["(0x%X, 0x%X, 0x%X)," % r for r in compositions], 4)
+
+
+main()
@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::core_dns::{DnsName, DnsPacket, DnsRecord, QueryClass, QueryType, ResultCode};
+
+// 默认最多缓存的条目数
+pub const DEFAULT_CACHE_SIZE: usize = 10000;
+
+// 缓存键: 域名(小写)、查询类型、查询类别
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub name: DnsName,
+    pub qtype: QueryType,
+    pub class: QueryClass,
+}
+
+impl CacheKey {
+    pub fn new(name: &DnsName, qtype: QueryType, class: QueryClass) -> CacheKey {
+        CacheKey {
+            name: name.to_lowercase(),
+            qtype,
+            class,
+        }
+    }
+}
+
+struct CacheEntry {
+    rescode: ResultCode,
+    authed_data: bool, // 上游是否验证过 DNSSEC 签名 (AD 位)
+    dnssec_ok: bool, // 向上游查询时是否设置了DO位, 即是否带有签名
+    answers: Vec<DnsRecord>,
+    authorities: Vec<DnsRecord>,
+    resources: Vec<DnsRecord>,
+    inserted: Instant,
+    expires: Instant,
+    last_used: u64, // LRU 序号, 越小越久没有被使用
+}
+
+impl CacheEntry {
+    // 按已经在缓存中停留的时间扣减TTL后返回记录
+    fn records(&self, records: &[DnsRecord], now: Instant) -> Vec<DnsRecord> {
+        let elapsed = now.duration_since(self.inserted).as_secs() as u32;
+        records
+            .iter()
+            .map(|rec| {
+                let mut rec = rec.clone();
+                rec.set_ttl(rec.ttl().saturating_sub(elapsed));
+                rec
+            })
+            .collect()
+    }
+}
+
+struct CacheInner {
+    entries: HashMap<CacheKey, CacheEntry>,
+    lru: BTreeMap<u64, CacheKey>,
+    tick: u64,
+}
+
+impl CacheInner {
+    fn touch(&mut self, key: &CacheKey) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.lru.remove(&entry.last_used);
+            entry.last_used = tick;
+            self.lru.insert(tick, key.clone());
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.lru.remove(&entry.last_used);
+        }
+    }
+
+    // 淘汰最久没有使用的条目
+    fn evict_lru(&mut self) {
+        let oldest = self.lru.keys().next().cloned();
+        if let Some(tick) = oldest {
+            if let Some(key) = self.lru.remove(&tick) {
+                self.entries.remove(&key);
+            }
+        }
+    }
+}
+
+// # Cache
+// 线程安全的应答缓存, 按记录的TTL过期, 超过容量时按LRU淘汰
+pub struct Cache {
+    inner: Mutex<CacheInner>,
+    max_entries: usize,
+}
+
+impl Cache {
+    // max_entries 为 0 时不缓存任何内容
+    pub fn new(max_entries: usize) -> Cache {
+        Cache {
+            inner: Mutex::new(CacheInner {
+                entries: HashMap::new(),
+                lru: BTreeMap::new(),
+                tick: 0,
+            }),
+            max_entries,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // 命中时返回一个TTL已经扣减过的应答包, 过期的条目会被顺便删除。
+    // 没有签名的条目不能用来回答设置了DO位的客户端; 反过来带签名的条目要去掉 DNSSEC 记录
+    pub fn lookup(&self, key: &CacheKey, dnssec_ok: bool) -> Option<DnsPacket> {
+        let mut inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+
+        let expired = match inner.entries.get(key) {
+            Some(entry) if dnssec_ok && !entry.dnssec_ok => return None,
+            Some(entry) => now >= entry.expires,
+            None => return None,
+        };
+        if expired {
+            inner.remove(key);
+            return None;
+        }
+        inner.touch(key);
+
+        let entry = &inner.entries[key];
+        let mut packet = DnsPacket::new();
+        packet.header.rescode = entry.rescode;
+        packet.header.authed_data = entry.authed_data;
+        packet.answers = entry.records(&entry.answers, now);
+        packet.authorities = entry.records(&entry.authorities, now);
+        packet.resources = entry.records(&entry.resources, now);
+        if entry.dnssec_ok && !dnssec_ok {
+            packet.strip_dnssec(key.qtype);
+        }
+
+        Some(packet)
+    }
+
+    // 缓存一个上游的应答, 有效期取所有记录中最小的TTL。
+    // NXDOMAIN 和没有回答记录的 NOERROR(NODATA) 按 RFC 2308 作为否定应答缓存。
+    // `dnssec_ok` 表示向上游查询时是否设置了DO位。
+    pub fn store(&self, key: CacheKey, packet: &DnsPacket, dnssec_ok: bool) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let mut authorities = packet.authorities.clone();
+        let ttl = match packet.header.rescode {
+            ResultCode::NXDOMAIN => negative_ttl(packet, &mut authorities),
+            ResultCode::NOERROR if packet.answers.is_empty() => negative_ttl(packet, &mut authorities),
+            ResultCode::NOERROR => min_ttl(packet.answers.iter().chain(authorities.iter())),
+            _ => None,
+        };
+        let ttl = match ttl {
+            Some(ttl) => ttl.min(min_ttl(packet.resources.iter()).unwrap_or(ttl)),
+            None => return,
+        };
+        if ttl == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove(&key);
+        while inner.entries.len() >= self.max_entries {
+            inner.evict_lru();
+        }
+
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.lru.insert(tick, key.clone());
+        inner.entries.insert(
+            key,
+            CacheEntry {
+                rescode: packet.header.rescode,
+                authed_data: packet.header.authed_data,
+                dnssec_ok,
+                answers: packet.answers.clone(),
+                authorities,
+                resources: packet.resources.clone(),
+                inserted: now,
+                expires: now + Duration::from_secs(ttl as u64),
+                last_used: tick,
+            },
+        );
+    }
+}
+
+fn min_ttl<'a, I: Iterator<Item = &'a DnsRecord>>(records: I) -> Option<u32> {
+    records.map(|rec| rec.ttl()).min()
+}
+
+// 否定应答的有效期为授权部分中 SOA 的 TTL 与 MINIMUM 字段中较小的一个 (RFC 2308 5),
+// 同时把 SOA 的 TTL 改成这个值, 让下游的解析器也能正确缓存。没有 SOA 时不缓存。
+fn negative_ttl(packet: &DnsPacket, authorities: &mut [DnsRecord]) -> Option<u32> {
+    let soa = authorities.iter_mut().find(|rec| matches!(rec, DnsRecord::SOA { .. }))?;
+    let ttl = match *soa {
+        DnsRecord::SOA { ttl, minimum, .. } => ttl.min(minimum),
+        _ => return None,
+    };
+    soa.set_ttl(ttl);
+
+    // 回答部分可能带有指向不存在域名的CNAME链
+    Some(min_ttl(packet.answers.iter()).map_or(ttl, |answer_ttl| answer_ttl.min(ttl)))
+}
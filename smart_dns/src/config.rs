@@ -0,0 +1,147 @@
+use std::error::Error;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::cache::DEFAULT_CACHE_SIZE;
+
+// 上游默认端口以及单次查询的默认超时、重试次数
+pub const DEFAULT_UPSTREAM_PORT: u16 = 53;
+pub const DEFAULT_UPSTREAM_TIMEOUT: Duration = Duration::from_secs(2);
+pub const DEFAULT_UPSTREAM_RETRIES: u32 = 1;
+// 每个客户端查询最多向上游发送的次数, 以及从收到查询到放弃(返回SERVFAIL)的总时间
+pub const DEFAULT_RETRY_BUDGET: u32 = 4;
+pub const DEFAULT_DEADLINE: Duration = Duration::from_secs(5);
+
+// # Upstream
+// 一个上游DNS服务器, 支持IPv4/IPv6和自定义端口
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upstream {
+    pub addr: SocketAddr,
+    pub timeout: Duration, // 每次尝试等待应答的时间
+    pub retries: u32,      // 超时后在同一服务器上重试的次数
+    pub randomize_case: bool, // 查询时随机化域名大小写 (DNS 0x20), 要求上游原样回显
+}
+
+impl Upstream {
+    pub fn new(addr: SocketAddr) -> Upstream {
+        Upstream {
+            addr,
+            timeout: DEFAULT_UPSTREAM_TIMEOUT,
+            retries: DEFAULT_UPSTREAM_RETRIES,
+            randomize_case: false,
+        }
+    }
+
+    // 解析 `upstream = ` 后面的内容, 例如:
+    // 1.1.1.1
+    // 8.8.8.8:5353 timeout=500 retries=3
+    // [2606:4700:4700::1111]:53
+    // 2001:4860:4860::8888 timeout=1000
+    // 9.9.9.9 randomize_case=true
+    pub fn parse(value: &str) -> Result<Upstream, Box<dyn Error>> {
+        let mut parts = value.split_whitespace();
+        let addr = parts.next().ok_or("Missing upstream address")?;
+        let addr = match addr.parse::<SocketAddr>() {
+            Ok(addr) => addr,
+            Err(_) => match addr.parse::<IpAddr>() {
+                Ok(ip) => SocketAddr::new(ip, DEFAULT_UPSTREAM_PORT),
+                Err(_) => return Err(format!("Invalid upstream address: {}", addr).into()),
+            },
+        };
+
+        let mut upstream = Upstream::new(addr);
+        for part in parts {
+            let (key, val) = split_pair(part, '=')
+                .ok_or_else(|| format!("Invalid upstream option: {}", part))?;
+            match key {
+                // 毫秒
+                "timeout" => upstream.timeout = Duration::from_millis(val.parse()?),
+                "retries" => upstream.retries = val.parse()?,
+                "randomize_case" => upstream.randomize_case = val.parse()?,
+                _ => return Err(format!("Unknown upstream option: {}", key).into()),
+            }
+        }
+
+        Ok(upstream)
+    }
+}
+
+// # Config
+// 配置文件为简单的 `key = value` 格式, `#` 开头的行为注释, `upstream` 可以出现多次
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub upstreams: Vec<Upstream>,
+    pub retry_budget: u32,  // 所有上游加起来的最大尝试次数
+    pub deadline: Duration, // 单个查询的总时限
+    pub cache_size: usize,  // 最多缓存的条目数, 0 表示关闭缓存
+    pub compression: bool,  // 应答中是否使用域名压缩, 调试时可以关闭
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            upstreams: vec![Upstream::new(SocketAddr::from(([1, 1, 1, 1], DEFAULT_UPSTREAM_PORT)))],
+            retry_budget: DEFAULT_RETRY_BUDGET,
+            deadline: DEFAULT_DEADLINE,
+            cache_size: DEFAULT_CACHE_SIZE,
+            compression: true,
+        }
+    }
+}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, Box<dyn Error>> {
+        let content = fs::read_to_string(path)?;
+        Config::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Config, Box<dyn Error>> {
+        let mut config = Config::default();
+        let mut upstreams = Vec::new();
+
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = split_pair(line, '=')
+                .ok_or_else(|| format!("line {}: expected `key = value`", i + 1))?;
+            match key {
+                "upstream" => upstreams.push(
+                    Upstream::parse(value).map_err(|e| format!("line {}: {}", i + 1, e))?,
+                ),
+                "retry_budget" => {
+                    config.retry_budget = value.parse().map_err(|e| format!("line {}: {}", i + 1, e))?
+                }
+                "cache_size" => {
+                    config.cache_size = value.parse().map_err(|e| format!("line {}: {}", i + 1, e))?
+                }
+                "compression" => {
+                    config.compression = value.parse().map_err(|e| format!("line {}: {}", i + 1, e))?
+                }
+                // 毫秒
+                "deadline" => {
+                    config.deadline = Duration::from_millis(
+                        value.parse().map_err(|e| format!("line {}: {}", i + 1, e))?,
+                    )
+                }
+                _ => return Err(format!("line {}: unknown key `{}`", i + 1, key).into()),
+            }
+        }
+
+        // 没有配置上游时使用默认值
+        if !upstreams.is_empty() {
+            config.upstreams = upstreams;
+        }
+
+        Ok(config)
+    }
+}
+
+fn split_pair(s: &str, delim: char) -> Option<(&str, &str)> {
+    let idx = s.find(delim)?;
+    Some((s[..idx].trim(), s[idx + 1..].trim()))
+}
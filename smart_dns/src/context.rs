@@ -0,0 +1,30 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::cache::Cache;
+use crate::config::{Config, Upstream};
+
+// # ServerContext
+// 所有处理线程共享的状态
+pub struct ServerContext {
+    pub config: Config,
+    pub cache: Cache,
+    next_upstream: AtomicUsize,
+}
+
+impl ServerContext {
+    pub fn new(config: Config) -> ServerContext {
+        ServerContext {
+            cache: Cache::new(config.cache_size),
+            config,
+            next_upstream: AtomicUsize::new(0),
+        }
+    }
+
+    // 按尝试顺序返回所有上游: 起点在配置的上游之间轮询, 把查询分摊到每个服务器上,
+    // 之后依次排列其余上游作为故障转移的备选
+    pub fn upstreams(&self) -> impl Iterator<Item = &Upstream> {
+        let upstreams = &self.config.upstreams;
+        let start = self.next_upstream.fetch_add(1, Ordering::Relaxed);
+        (0..upstreams.len()).map(move |i| &upstreams[(start + i) % upstreams.len()])
+    }
+}
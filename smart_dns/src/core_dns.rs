@@ -1,44 +1,120 @@
-use std::error::Error;
-use std::net::{Ipv4Addr, Ipv6Addr, UdpSocket, SocketAddr};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::fmt;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+use std::str::FromStr;
+use std::net::{Ipv4Addr, Ipv6Addr, UdpSocket, SocketAddr, TcpStream};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::cache::CacheKey;
+use crate::config::Upstream;
+use crate::context::ServerContext;
+use crate::error::DnsError;
+use crate::idna;
+
+// 经典UDP报文的最大长度
+pub const UDP_PACKET_SIZE: usize = 512;
+// TCP报文(2字节长度前缀)以及EDNS所能承载的最大长度
+pub const MAX_PACKET_SIZE: usize = 65535;
+// 我们通过EDNS向客户端和上游宣告的UDP负载大小 (DNS Flag Day 2020 推荐值)
+pub const EDNS_PAYLOAD_SIZE: usize = 1232;
 
 pub struct BytePacketBuffer {
-    pub buf: [u8; 512],
+    pub buf: Vec<u8>,
     pub pos: usize, // 当前读取到的位置
+    limit: usize,   // 缓冲区允许增长到的最大长度
+    // 域名压缩表: 已写入的域名后缀(原样的标签, 区分大小写) -> 在报文中的偏移量
+    names: HashMap<Vec<Vec<u8>>, usize>,
+    compression: bool,
+}
+
+impl Default for BytePacketBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl BytePacketBuffer {
+    // 经典的512字节UDP缓冲区
     pub fn new() -> BytePacketBuffer {
+        BytePacketBuffer::with_size(UDP_PACKET_SIZE)
+    }
+
+    // 预先分配好`size`字节的定长缓冲区, 可直接交给`recv_from`使用
+    pub fn with_size(size: usize) -> BytePacketBuffer {
+        let size = size.min(MAX_PACKET_SIZE);
+        BytePacketBuffer {
+            buf: vec![0; size],
+            pos: 0,
+            limit: size,
+            names: HashMap::new(),
+            compression: true,
+        }
+    }
+
+    // 可增长的缓冲区: 初始为空, 写入时按需增长, 最多到`limit`字节 (TCP 使用 MAX_PACKET_SIZE)
+    pub fn growable(limit: usize) -> BytePacketBuffer {
+        BytePacketBuffer {
+            buf: Vec::new(),
+            pos: 0,
+            limit: limit.min(MAX_PACKET_SIZE),
+            names: HashMap::new(),
+            compression: true,
+        }
+    }
+
+    // 用已经收到的数据构造一个只读的缓冲区
+    pub fn from_bytes(data: &[u8]) -> BytePacketBuffer {
+        let data = &data[..data.len().min(MAX_PACKET_SIZE)];
         BytePacketBuffer {
-            buf: [0; 512],
+            buf: data.to_vec(),
             pos: 0,
+            limit: data.len(),
+            names: HashMap::new(),
+            compression: true,
         }
     }
 
+    // 缓冲区允许的最大长度
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    // 开启/关闭写入时的域名压缩 (默认开启, 调试时可以关闭以便直接查看完整的标签)
+    pub fn set_compression(&mut self, enabled: bool) {
+        self.compression = enabled;
+    }
+
     // 缓冲区内的当前位置
     fn pos(&self) -> usize {
         self.pos
     }
 
+    // 清空已写入的内容, 以便重新写入一个报文
+    fn reset(&mut self) {
+        self.pos = 0;
+        self.names.clear();
+    }
+
     // 将缓冲区位置向前步进特定的步数
-    fn step(&mut self, steps: usize) -> Result<(), Box<dyn Error>> {
+    fn step(&mut self, steps: usize) -> Result<(), DnsError> {
         self.pos += steps;
         Ok(())
     }
 
     // 更改缓冲区位置
-    fn seek(&mut self, pos: usize) -> Result<(), Box<dyn Error>> {
+    fn seek(&mut self, pos: usize) -> Result<(), DnsError> {
         self.pos = pos;
         Ok(())
     }
 
     // 读取一个字节并将位置向前移动一步
-    fn read(&mut self) -> Result<u8, Box<dyn Error>> {
-        if self.pos > 512 {
-            return Err("End of buffer".into());
-        }
-        if self.pos > self.buf.len() {
-            return Err("Array out of bounds".into());
+    fn read(&mut self) -> Result<u8, DnsError> {
+        if self.pos >= self.buf.len() {
+            return Err(DnsError::BufferOverrun);
         }
         let res = self.buf[self.pos];
         self.pos += 1;
@@ -46,35 +122,49 @@ impl BytePacketBuffer {
     }
 
     // 获取单个字节，而不更改缓冲区位置
-    fn get(&mut self, pos: usize) -> Result<u8, Box<dyn Error>> {
-        if pos >= 512 {
-            return Err("End of buffer".into());
+    fn get(&mut self, pos: usize) -> Result<u8, DnsError> {
+        if pos >= self.buf.len() {
+            return Err(DnsError::BufferOverrun);
         }
 
         Ok(self.buf[pos])
     }
 
     // 获取一个字节范围
-    fn get_range(&mut self, start: usize, len: usize) -> Result<&[u8], Box<dyn Error>> {
-        if start + len >= 512 {
-            return Err("End of buffer".into());
+    fn get_range(&mut self, start: usize, len: usize) -> Result<&[u8], DnsError> {
+        if start + len > self.buf.len() {
+            return Err(DnsError::BufferOverrun);
         }
-        Ok(&self.buf[start..start + len as usize])
+        Ok(&self.buf[start..start + len])
+    }
+
+    // 读取`len`个字节并前进
+    fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>, DnsError> {
+        let res = self.get_range(self.pos, len)?.to_vec();
+        self.step(len)?;
+
+        Ok(res)
+    }
+
+    // 读取一个 <character-string>: 1字节长度加上最多255字节的内容
+    fn read_character_string(&mut self) -> Result<Vec<u8>, DnsError> {
+        let len = self.read()? as usize;
+        self.read_bytes(len)
     }
 
     // 读取两个字节，向前迈出两步
-    fn read_u16(&mut self) -> Result<u16, Box<dyn Error>> {
+    fn read_u16(&mut self) -> Result<u16, DnsError> {
         let res = ((self.read()? as u16) << 8) | (self.read()? as u16);
 
         Ok(res)
     }
 
     // 读取四个字节，前进四步
-    fn read_u32(&mut self) -> Result<u32, Box<dyn Error>> {
+    fn read_u32(&mut self) -> Result<u32, DnsError> {
         let res = ((self.read()? as u32) << 24)
             | ((self.read()? as u32) << 16)
             | ((self.read()? as u32) << 8)
-            | ((self.read()? as u32) << 0);
+            | (self.read()? as u32);
 
         Ok(res)
     }
@@ -82,8 +172,8 @@ impl BytePacketBuffer {
     // Read a qname
     // 棘手的部分：读取域名，并考虑标签。
     // 将采用[3] www [6] google [3] com [0]之类的内容并附加
-    // www.google.com to outstr.
-    fn read_qname(&mut self, outstr: &mut String) -> Result<(), Box<dyn Error>> {
+    // www.google.com.
+    fn read_name(&mut self) -> Result<DnsName, DnsError> {
         // 由于我们可能会遇到跳跃，因此我们将跟踪自己的位置本地使用，而不是使用结构中的位置。
         // 这使我们可以将共同的立场移动到当前立场之上 qname，同时跟踪当前qname的进度使用此变量。
         let mut pos = self.pos;
@@ -93,21 +183,25 @@ impl BytePacketBuffer {
         let max_jumps = 5;
         let mut jumps_performed = 0;
 
-        // 我们为每个标签附加的定界符。由于我们不希望在域名的开头加点，因此我们暂时将其保留为
-        // 空并将其设置为“”。 在第一次迭代结束时。
-        let mut delim = "";
+        // 已读到的标签, 以及它们编码后的总长度 (包括结尾的0)
+        let mut labels = Vec::new();
+        let mut wire_len = 1;
         loop {
             // Dns数据包是不受信任的数据，因此我们需要保持偏执。
             // 有人可以在跳转指令中循环制作一个数据包。
             // 这可以防止此类数据包。
             // (防止恶意数据包触发无限循环)
             if jumps_performed > max_jumps {
-                return Err(format!("Limit of {} jumps exceeded", max_jumps).into());
+                return Err(DnsError::PointerLoop);
             }
 
             // 在这一点上，我们始终处在标签的开头。
             // 回想一下，标签以长度字节开头。
             let len = self.get(pos)?;
+            // 最高两位为 01 或 10 的长度字节是保留的扩展标签类型, 不予支持
+            if len & 0xC0 == 0x40 || len & 0xC0 == 0x80 {
+                return Err(DnsError::LabelTooLong(len as usize));
+            }
 
             // 如果len的最高两位被置位，则表示跳到数据包中的其他一些偏移量：
             if (len & 0xC0) == 0xC0 {
@@ -135,14 +229,15 @@ impl BytePacketBuffer {
                 if len == 0 {
                     break;
                 }
-                // 首先将定界符追加到我们的输出缓冲区。
-                outstr.push_str(delim);
-
-                // 提取此标签的实际ASCII字节并将其附加到输出缓冲区。
-                let str_buffer = self.get_range(pos, len as usize)?;
-                outstr.push_str(&String::from_utf8_lossy(str_buffer).to_lowercase());
+                // 展开压缩指针后的完整域名同样不能超过255字节
+                wire_len += len as usize + 1;
+                if wire_len > MAX_NAME_LEN {
+                    return Err(DnsError::NameTooLong(wire_len));
+                }
 
-                delim = ".";
+                // 提取此标签的原始字节。
+                let label = self.get_range(pos, len as usize)?;
+                labels.push(label.to_vec());
 
                 // 向前移动标签的全长
                 pos += len as usize;
@@ -153,64 +248,108 @@ impl BytePacketBuffer {
             self.seek(pos)?;
         }
 
-        Ok(())
+        Ok(DnsName { labels })
     }
 
     // # transmitter dns
-    fn write(&mut self,val: u8) -> Result<(),Box<dyn Error>> {
-        if self.pos >= 512 {
-            return Err("End of buffer".into())
+    fn write(&mut self,val: u8) -> Result<(),DnsError> {
+        if self.pos >= self.limit {
+            return Err(DnsError::BufferOverrun);
+        }
+        // 可增长的缓冲区在写到末尾时扩容
+        if self.pos >= self.buf.len() {
+            self.buf.resize(self.pos + 1, 0);
         }
         self.buf[self.pos] = val;
         self.pos += 1;
         Ok(())
     }
-    fn write_u8(&mut self,val: u8) -> Result<(),Box<dyn Error>> {
+    fn write_u8(&mut self,val: u8) -> Result<(),DnsError> {
         self.write(val)?;
 
         Ok(())
     }
-    fn write_u16(&mut self,val: u16) -> Result<(),Box<dyn Error>> {
+    fn write_u16(&mut self,val: u16) -> Result<(),DnsError> {
         self.write( (val >> 8) as u8 )?;
         self.write( (val & 0xFF) as u8 )?;
 
         Ok(())
     }
-    fn write_u32(&mut self, val: u32) -> Result<(),Box<dyn Error>> {
+    fn write_u32(&mut self, val: u32) -> Result<(),DnsError> {
         self.write(((val >> 24) & 0xFF) as u8)?;
         self.write(((val >> 16) & 0xFF) as u8)?;
         self.write(((val >> 8) & 0xFF) as u8)?;
-        self.write(((val >> 0) & 0xFF) as u8)?;
+        self.write((val & 0xFF) as u8)?;
+
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, data: &[u8]) -> Result<(),DnsError> {
+        for b in data {
+            self.write(*b)?;
+        }
 
         Ok(())
     }
 
+    fn write_character_string(&mut self, data: &[u8]) -> Result<(),DnsError> {
+        if data.len() > 255 {
+            return Err(DnsError::InvalidData("character string exceeds 255 bytes".to_string()));
+        }
+        self.write_u8(data.len() as u8)?;
+        self.write_bytes(data)
+    }
+
     // 我们还需要一个函数以标签形式编写查询名称：
-    fn write_qname(&mut self,qname: &str) -> Result<(),Box<dyn Error>> {
-        for label in qname.split('.') {
-            let len = label.len();
-            if len > 0x34 {
-                return Err("Single label exceeds 63 characters of length".into());
-            }
+    // 如果某个后缀之前已经写过, 就用指向它的 0xC0 指针代替剩余的标签(RFC 1035 4.1.4)
+    fn write_name(&mut self,qname: &DnsName) -> Result<(),DnsError> {
+        self.write_labels(qname, true)
+    }
+
+    // 不允许压缩的域名 (例如 SRV 的目标, RFC 2782), 但仍然可以被后面的域名引用
+    fn write_name_uncompressed(&mut self,qname: &DnsName) -> Result<(),DnsError> {
+        self.write_labels(qname, false)
+    }
 
-            self.write_u8(len as u8)?;
-            for b in label.as_bytes() {
-                self.write_u8(*b)?;
+    // DnsName 在构造时已经检查过标签和总长度, 这里直接写出
+    fn write_labels(&mut self,qname: &DnsName, allow_pointer: bool) -> Result<(),DnsError> {
+        let labels = qname.labels();
+        for (i, label) in labels.iter().enumerate() {
+            if self.compression {
+                // 只有大小写也完全相同的后缀才能共用, 否则读出时会变成前一个域名的大小写
+                let suffix = labels[i..].to_vec();
+                if let Some(&offset) = self.names.get(&suffix) {
+                    if allow_pointer {
+                        self.write_u16(0xC000 | offset as u16)?;
+                        return Ok(());
+                    }
+                }
+                // 指针只有14位, 超出范围的位置无法被引用
+                if self.pos <= 0x3FFF {
+                    self.names.insert(suffix, self.pos);
+                }
             }
+
+            self.write_u8(label.len() as u8)?;
+            self.write_bytes(label)?;
         }
 
+        // 以根域的空标签结束
         self.write_u8(0)?;
         Ok(())
     }
 
     // add type
-    fn set(&mut self, pos: usize, val: u8) -> Result<(),Box<dyn Error>> {
+    fn set(&mut self, pos: usize, val: u8) -> Result<(),DnsError> {
+        if pos >= self.buf.len() {
+            return Err(DnsError::BufferOverrun);
+        }
         self.buf[pos] = val;
 
         Ok(())
     }
 
-    fn set_u16(&mut self, pos: usize, val: u16) -> Result<(),Box<dyn Error>> {
+    fn set_u16(&mut self, pos: usize, val: u16) -> Result<(),DnsError> {
         self.set(pos, (val >> 8) as u8)?;
         self.set(pos + 1, (val & 0xFF) as u8)?;
 
@@ -220,25 +359,121 @@ impl BytePacketBuffer {
 
 // # ResultCode
 // 在转到标题之前，我们将为rescode字段的值添加一个枚举
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+// 报头中只有低4位, 使用 EDNS 时 OPT 记录中还有高8位, 合起来是12位 (RFC 6891 6.1.3)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum ResultCode {
-    NOERROR = 0,
-    FORMERR = 1,
-    SERVFAIL = 2,
-    NXDOMAIN = 3,
-    NOTIMP = 4,
-    REFUSED = 5,
+    UNKNOWN(u16),
+    NOERROR,   // 0
+    FORMERR,   // 1
+    SERVFAIL,  // 2
+    NXDOMAIN,  // 3
+    NOTIMP,    // 4
+    REFUSED,   // 5
+    YXDOMAIN,  // 6
+    YXRRSET,   // 7
+    NXRRSET,   // 8
+    NOTAUTH,   // 9
+    NOTZONE,   // 10
+    DSOTYPENI, // 11
+    BADVERS,   // 16, 只能通过 EDNS 表示
+    BADKEY,    // 17
+    BADTIME,   // 18
+    BADMODE,   // 19
+    BADNAME,   // 20
+    BADALG,    // 21
+    BADTRUNC,  // 22
+    BADCOOKIE, // 23
 }
 
 impl ResultCode {
-    pub fn from_num(num: u8) -> ResultCode {
+    pub fn to_num(&self) -> u16 {
+        match *self {
+            ResultCode::UNKNOWN(x) => x,
+            ResultCode::NOERROR => 0,
+            ResultCode::FORMERR => 1,
+            ResultCode::SERVFAIL => 2,
+            ResultCode::NXDOMAIN => 3,
+            ResultCode::NOTIMP => 4,
+            ResultCode::REFUSED => 5,
+            ResultCode::YXDOMAIN => 6,
+            ResultCode::YXRRSET => 7,
+            ResultCode::NXRRSET => 8,
+            ResultCode::NOTAUTH => 9,
+            ResultCode::NOTZONE => 10,
+            ResultCode::DSOTYPENI => 11,
+            ResultCode::BADVERS => 16,
+            ResultCode::BADKEY => 17,
+            ResultCode::BADTIME => 18,
+            ResultCode::BADMODE => 19,
+            ResultCode::BADNAME => 20,
+            ResultCode::BADALG => 21,
+            ResultCode::BADTRUNC => 22,
+            ResultCode::BADCOOKIE => 23,
+        }
+    }
+
+    pub fn from_num(num: u16) -> ResultCode {
         match num {
+            0 => ResultCode::NOERROR,
             1 => ResultCode::FORMERR,
             2 => ResultCode::SERVFAIL,
             3 => ResultCode::NXDOMAIN,
             4 => ResultCode::NOTIMP,
             5 => ResultCode::REFUSED,
-            0 | _ => ResultCode::NOERROR,
+            6 => ResultCode::YXDOMAIN,
+            7 => ResultCode::YXRRSET,
+            8 => ResultCode::NXRRSET,
+            9 => ResultCode::NOTAUTH,
+            10 => ResultCode::NOTZONE,
+            11 => ResultCode::DSOTYPENI,
+            16 => ResultCode::BADVERS,
+            17 => ResultCode::BADKEY,
+            18 => ResultCode::BADTIME,
+            19 => ResultCode::BADMODE,
+            20 => ResultCode::BADNAME,
+            21 => ResultCode::BADALG,
+            22 => ResultCode::BADTRUNC,
+            23 => ResultCode::BADCOOKIE,
+            _ => ResultCode::UNKNOWN(num),
+        }
+    }
+}
+
+// # Opcode
+// 报头中的4位操作码, 我们只处理标准查询
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    UNKNOWN(u8),
+    QUERY,  // 0
+    IQUERY, // 1, 已废弃 (RFC 3425)
+    STATUS, // 2
+    NOTIFY, // 4
+    UPDATE, // 5
+    DSO,    // 6
+}
+
+impl Opcode {
+    pub fn to_num(&self) -> u8 {
+        match *self {
+            Opcode::UNKNOWN(x) => x,
+            Opcode::QUERY => 0,
+            Opcode::IQUERY => 1,
+            Opcode::STATUS => 2,
+            Opcode::NOTIFY => 4,
+            Opcode::UPDATE => 5,
+            Opcode::DSO => 6,
+        }
+    }
+
+    pub fn from_num(num: u8) -> Opcode {
+        match num {
+            0 => Opcode::QUERY,
+            1 => Opcode::IQUERY,
+            2 => Opcode::STATUS,
+            4 => Opcode::NOTIFY,
+            5 => Opcode::UPDATE,
+            6 => Opcode::DSO,
+            _ => Opcode::UNKNOWN(num),
         }
     }
 }
@@ -255,7 +490,7 @@ pub struct DnsHeader {
     // 1 bit
     pub authoritative_answer: bool,
     // 1 bit
-    pub opcode: u8,
+    pub opcode: Opcode,
     // 4 bits
     pub response: bool,             // 1 bit
 
@@ -277,6 +512,12 @@ pub struct DnsHeader {
     // 16 bits
     pub resource_entries: u16,      // 16 bits
 }
+impl Default for DnsHeader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 // 该实现涉及很多琐事：
 impl DnsHeader {
     pub fn new() -> DnsHeader {
@@ -286,7 +527,7 @@ impl DnsHeader {
             recursion_desired: false,
             truncated_message: false,
             authoritative_answer: false,
-            opcode: 0,
+            opcode: Opcode::QUERY,
             response: false,
 
             rescode: ResultCode::NOERROR,
@@ -302,7 +543,7 @@ impl DnsHeader {
         }
     }
 
-    pub fn read(&mut self, buffer: &mut BytePacketBuffer) -> Result<(), Box<dyn Error>> {
+    pub fn read(&mut self, buffer: &mut BytePacketBuffer) -> Result<(), DnsError> {
         self.id = buffer.read_u16()?;
 
         let flags = buffer.read_u16()?;
@@ -312,10 +553,11 @@ impl DnsHeader {
         self.recursion_desired = (a & (1 << 0)) > 0;
         self.truncated_message = (a & (1 << 1)) > 0;
         self.authoritative_answer = (a & (1 << 2)) > 0;
-        self.opcode = (a >> 3) & 0x0F;
+        self.opcode = Opcode::from_num((a >> 3) & 0x0F);
         self.response = (a & (1 << 7)) > 0;
 
-        self.rescode = ResultCode::from_num(b & 0x0F);
+        // 使用 EDNS 时由 DnsPacket::from_buffer 再合并 OPT 中的高8位
+        self.rescode = ResultCode::from_num((b & 0x0F) as u16);
         self.checking_disabled = (b & (1 << 4)) > 0;
         self.authed_data = (b & (1 << 5)) > 0;
         self.z = (b & (1 << 6)) > 0;
@@ -332,19 +574,19 @@ impl DnsHeader {
 
     // # 扩展DnsHeader以进行编写
     // 基于我们的新功能，我们可以扩展协议表示结构。从DnsHeader开始：
-    pub fn write(&self,buffer: &mut BytePacketBuffer) -> Result<(),Box<dyn Error>> {
+    pub fn write(&self,buffer: &mut BytePacketBuffer) -> Result<(),DnsError> {
         buffer.write_u16(self.id)?;
 
         buffer.write_u8(
             (self.recursion_desired as u8)
                 | ((self.truncated_message as u8) << 1)
                 | ((self.authoritative_answer as u8) << 2)
-                | (self.opcode << 3)
-                | ((self.response as u8) << 7) as u8,
+                | ((self.opcode.to_num() & 0x0F) << 3)
+                | ((self.response as u8) << 7),
         )?;
 
         buffer.write_u8(
-            (self.rescode as u8)
+            ((self.rescode.to_num() & 0x0F) as u8)
                 | ((self.checking_disabled as u8) << 4)
                 | ((self.authed_data as u8) << 5)
                 | ((self.z as u8) << 6)
@@ -369,8 +611,25 @@ pub enum QueryType {
     A, // 1
     NS,    // 2
     CNAME, // 5
+    SOA,   // 6
+    PTR,   // 12
     MX,    // 15
+    TXT,   // 16
     AAAA,  // 28
+    SRV,   // 33
+    NAPTR, // 35
+    DS,    // 43
+    SSHFP, // 44
+    RRSIG, // 46
+    NSEC,  // 47
+    DNSKEY, // 48
+    NSEC3, // 50
+    NSEC3PARAM, // 51
+    TLSA,  // 52
+    SVCB,  // 64
+    HTTPS, // 65
+    CAA,   // 257
+    OPT,   // 41
 }
 impl QueryType {
     pub fn to_num(&self) -> u16 {
@@ -379,8 +638,25 @@ impl QueryType {
             QueryType::A => 1,
             QueryType::NS => 2,
             QueryType::CNAME => 5,
+            QueryType::SOA => 6,
+            QueryType::PTR => 12,
             QueryType::MX => 15,
+            QueryType::TXT => 16,
             QueryType::AAAA => 28,
+            QueryType::SRV => 33,
+            QueryType::NAPTR => 35,
+            QueryType::DS => 43,
+            QueryType::SSHFP => 44,
+            QueryType::RRSIG => 46,
+            QueryType::NSEC => 47,
+            QueryType::DNSKEY => 48,
+            QueryType::NSEC3 => 50,
+            QueryType::NSEC3PARAM => 51,
+            QueryType::TLSA => 52,
+            QueryType::SVCB => 64,
+            QueryType::HTTPS => 65,
+            QueryType::CAA => 257,
+            QueryType::OPT => 41,
         }
     }
 
@@ -389,111 +665,1155 @@ impl QueryType {
             1 => QueryType::A,
             2 => QueryType::NS,
             5 => QueryType::CNAME,
+            6 => QueryType::SOA,
+            12 => QueryType::PTR,
             15 => QueryType::MX,
+            16 => QueryType::TXT,
             28 => QueryType::AAAA,
+            33 => QueryType::SRV,
+            35 => QueryType::NAPTR,
+            43 => QueryType::DS,
+            44 => QueryType::SSHFP,
+            46 => QueryType::RRSIG,
+            47 => QueryType::NSEC,
+            48 => QueryType::DNSKEY,
+            50 => QueryType::NSEC3,
+            51 => QueryType::NSEC3PARAM,
+            52 => QueryType::TLSA,
+            64 => QueryType::SVCB,
+            65 => QueryType::HTTPS,
+            257 => QueryType::CAA,
+            41 => QueryType::OPT,
             _ => QueryType::UNKNOWN(num),
         }
     }
+
+    // RDATA 中没有可压缩域名的类型, 解析失败时可以把原始字节当作未知类型转发
+    pub fn is_opaque(&self) -> bool {
+        matches!(
+            *self,
+            QueryType::NSEC | QueryType::NSEC3 | QueryType::SVCB | QueryType::HTTPS | QueryType::CAA
+        )
+    }
+}
+
+// 区域文件中的写法, 未知类型写成 TYPE123 (RFC 3597 5)
+impl fmt::Display for QueryType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            QueryType::UNKNOWN(x) => write!(f, "TYPE{}", x),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+impl FromStr for QueryType {
+    type Err = DnsError;
+
+    fn from_str(s: &str) -> Result<QueryType,DnsError> {
+        let upper = s.to_ascii_uppercase();
+        let qtype = match upper.as_str() {
+            "A" => QueryType::A,
+            "NS" => QueryType::NS,
+            "CNAME" => QueryType::CNAME,
+            "SOA" => QueryType::SOA,
+            "PTR" => QueryType::PTR,
+            "MX" => QueryType::MX,
+            "TXT" => QueryType::TXT,
+            "AAAA" => QueryType::AAAA,
+            "SRV" => QueryType::SRV,
+            "NAPTR" => QueryType::NAPTR,
+            "DS" => QueryType::DS,
+            "SSHFP" => QueryType::SSHFP,
+            "RRSIG" => QueryType::RRSIG,
+            "NSEC" => QueryType::NSEC,
+            "DNSKEY" => QueryType::DNSKEY,
+            "NSEC3" => QueryType::NSEC3,
+            "NSEC3PARAM" => QueryType::NSEC3PARAM,
+            "TLSA" => QueryType::TLSA,
+            "SVCB" => QueryType::SVCB,
+            "HTTPS" => QueryType::HTTPS,
+            "CAA" => QueryType::CAA,
+            "OPT" => QueryType::OPT,
+            _ => match upper.strip_prefix("TYPE").and_then(|n| n.parse().ok()) {
+                Some(num) => QueryType::from_num(num),
+                None => return Err(DnsError::Syntax(format!("unknown type: {}", s))),
+            },
+        };
+        Ok(qtype)
+    }
+}
+
+// # QueryClass
+// 记录的类别, 几乎总是 IN; CH 用于 version.bind 之类的查询, NONE/ANY 用于动态更新 (RFC 2136)
+#[derive(PartialEq, Eq, Debug, Clone, Hash, Copy, PartialOrd, Ord)]
+pub enum QueryClass {
+    UNKNOWN(u16),
+    IN,   // 1
+    CH,   // 3
+    HS,   // 4
+    NONE, // 254
+    ANY,  // 255
+}
+impl QueryClass {
+    pub fn to_num(&self) -> u16 {
+        match *self {
+            QueryClass::UNKNOWN(x) => x,
+            QueryClass::IN => 1,
+            QueryClass::CH => 3,
+            QueryClass::HS => 4,
+            QueryClass::NONE => 254,
+            QueryClass::ANY => 255,
+        }
+    }
+
+    pub fn from_num(num: u16) -> QueryClass {
+        match num {
+            1 => QueryClass::IN,
+            3 => QueryClass::CH,
+            4 => QueryClass::HS,
+            254 => QueryClass::NONE,
+            255 => QueryClass::ANY,
+            _ => QueryClass::UNKNOWN(num),
+        }
+    }
+}
+
+// 区域文件中的写法, 未知类别写成 CLASS123 (RFC 3597 5)
+impl fmt::Display for QueryClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            QueryClass::UNKNOWN(x) => write!(f, "CLASS{}", x),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+impl FromStr for QueryClass {
+    type Err = DnsError;
+
+    fn from_str(s: &str) -> Result<QueryClass,DnsError> {
+        let upper = s.to_ascii_uppercase();
+        let class = match upper.as_str() {
+            "IN" => QueryClass::IN,
+            "CH" => QueryClass::CH,
+            "HS" => QueryClass::HS,
+            "NONE" => QueryClass::NONE,
+            "ANY" => QueryClass::ANY,
+            _ => match upper.strip_prefix("CLASS").and_then(|n| n.parse().ok()) {
+                Some(num) => QueryClass::from_num(num),
+                None => return Err(DnsError::Syntax(format!("unknown class: {}", s))),
+            },
+        };
+        Ok(class)
+    }
+}
+
+// 单个标签和整个域名(线路格式)的最大长度 (RFC 1035 2.3.4)
+pub const MAX_LABEL_LEN: usize = 63;
+pub const MAX_NAME_LEN: usize = 255;
+
+// # DnsName
+// 域名, 按标签保存原始字节, 不含结尾的空标签。
+// 只能通过校验过的方式构造, 所以写入报文时不需要再检查长度。
+// 保留原始大小写, 但比较和哈希时不区分大小写 (RFC 4343)。
+#[derive(Clone, Default)]
+pub struct DnsName {
+    labels: Vec<Vec<u8>>,
+}
+
+impl DnsName {
+    // 根域 "."
+    pub fn root() -> DnsName {
+        DnsName { labels: Vec::new() }
+    }
+
+    // 检查每个标签为1到63字节, 整个域名编码后不超过255字节
+    pub fn from_labels(labels: Vec<Vec<u8>>) -> Result<DnsName,DnsError> {
+        let name = DnsName { labels };
+        for label in &name.labels {
+            if label.is_empty() {
+                return Err(DnsError::InvalidData("empty label in domain name".to_string()));
+            }
+            if label.len() > MAX_LABEL_LEN {
+                return Err(DnsError::LabelTooLong(label.len()));
+            }
+        }
+        if name.wire_len() > MAX_NAME_LEN {
+            return Err(DnsError::NameTooLong(name.wire_len()));
+        }
+        Ok(name)
+    }
+
+    pub fn labels(&self) -> &[Vec<u8>] {
+        &self.labels
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    // 不压缩时在报文中占用的字节数: 每个标签前有一个长度字节, 最后是根标签的0
+    pub fn wire_len(&self) -> usize {
+        self.labels.iter().map(|label| label.len() + 1).sum::<usize>() + 1
+    }
+
+    pub fn to_lowercase(&self) -> DnsName {
+        DnsName {
+            labels: self.labels.iter().map(|label| label.to_ascii_lowercase()).collect(),
+        }
+    }
+
+    // 给人看的形式: A-label 解码成 Unicode, 其余标签和 Display 一样转义
+    pub fn to_unicode(&self) -> String {
+        if self.is_root() {
+            return ".".to_string();
+        }
+        let mut out = String::new();
+        for (i, label) in self.labels.iter().enumerate() {
+            if i > 0 {
+                out.push('.');
+            }
+            match std::str::from_utf8(label).ok().and_then(idna::to_unicode) {
+                Some(unicode) => out.push_str(&unicode),
+                None => escape_label(label, &mut out),
+            }
+        }
+        out
+    }
+
+    // 含有 A-label 时返回 Unicode 形式, 供日志附在转义形式后面; 两者相同时返回 None
+    pub fn unicode_hint(&self) -> Option<String> {
+        let unicode = self.to_unicode();
+        if unicode == self.to_string() {
+            None
+        } else {
+            Some(unicode)
+        }
+    }
+
+    // 区分大小写的比较, 用于校验上游是否原样回显了 0x20 随机化后的域名
+    pub fn eq_exact(&self, other: &DnsName) -> bool {
+        self.labels == other.labels
+    }
+
+    // 按`bits`逐个翻转字母的大小写 (DNS 0x20), 非字母字节不变
+    fn randomize_case(&self, bits: &[u8]) -> DnsName {
+        let mut bits = bits.iter().cycle();
+        DnsName {
+            labels: self.labels.iter().map(|label| {
+                label.iter().map(|&b| match bits.next() {
+                    Some(bit) if bit & 1 == 1 && b.is_ascii_alphabetic() => b ^ 0x20,
+                    _ => b,
+                }).collect()
+            }).collect(),
+        }
+    }
+}
+
+impl PartialEq for DnsName {
+    fn eq(&self, other: &DnsName) -> bool {
+        self.labels.len() == other.labels.len()
+            && self.labels.iter().zip(&other.labels).all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+}
+
+impl Eq for DnsName {}
+
+impl Hash for DnsName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // 与 eq 一致: 只对小写形式做哈希
+        for label in &self.labels {
+            state.write_usize(label.len());
+            for b in label {
+                state.write_u8(b.to_ascii_lowercase());
+            }
+        }
+    }
+}
+
+impl PartialOrd for DnsName {
+    fn partial_cmp(&self, other: &DnsName) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DnsName {
+    fn cmp(&self, other: &DnsName) -> Ordering {
+        self.to_lowercase().labels.cmp(&other.to_lowercase().labels)
+    }
+}
+
+// 解析 "www.example.com" 或 "www.example.com." 形式的域名, "" 和 "." 都表示根域。
+// 支持 RFC 1035 5.1 的转义: `\DDD` 为十进制表示的任意字节, `\X` 为字符 X 本身 (例如 `\.` 是标签中的点)。
+// 含有(未转义的)非ASCII字符的标签按 IDNA 转换成 A-label, 例如 "中国" 变成 "xn--fiqs8s"。
+impl FromStr for DnsName {
+    type Err = DnsError;
+
+    fn from_str(s: &str) -> Result<DnsName,DnsError> {
+        let s = idna::map_dots(s);
+        let s = s.as_ref();
+        if s.is_empty() || s == "." {
+            return Ok(DnsName::root());
+        }
+
+        let bytes = s.as_bytes();
+        let mut labels = Vec::new();
+        let mut label = Vec::new();
+        let mut unicode = false;
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'.' => {
+                    if label.is_empty() {
+                        return Err(DnsError::InvalidData(format!("empty label in domain name: {}", s)));
+                    }
+                    labels.push(finish_label(std::mem::take(&mut label), unicode)?);
+                    unicode = false;
+                    i += 1;
+                }
+                b'\\' => {
+                    let rest = &bytes[i + 1..];
+                    if rest.len() >= 3 && rest[..3].iter().all(u8::is_ascii_digit) {
+                        let value = rest[..3].iter().fold(0u16, |n, d| n * 10 + (d - b'0') as u16);
+                        if value > 255 {
+                            return Err(DnsError::InvalidData(format!("invalid escape in domain name: {}", s)));
+                        }
+                        label.push(value as u8);
+                        i += 4;
+                    } else if let Some(&c) = rest.first() {
+                        label.push(c);
+                        i += 2;
+                    } else {
+                        return Err(DnsError::InvalidData(format!("trailing backslash in domain name: {}", s)));
+                    }
+                }
+                b => {
+                    unicode |= !b.is_ascii();
+                    label.push(b);
+                    i += 1;
+                }
+            }
+        }
+        // 最后一个标签为空说明域名以点结尾 (绝对域名)
+        if !label.is_empty() {
+            labels.push(finish_label(label, unicode)?);
+        }
+
+        DnsName::from_labels(labels)
+    }
+}
+
+// 文本中直接写出的 Unicode 标签转换成 A-label, 其余标签保持原样
+fn finish_label(label: Vec<u8>, unicode: bool) -> Result<Vec<u8>,DnsError> {
+    if !unicode {
+        return Ok(label);
+    }
+    let label = String::from_utf8(label)
+        .map_err(|_| DnsError::InvalidData("escaped bytes mixed with Unicode in label".to_string()))?;
+    Ok(idna::to_ascii(&label)?.into_bytes())
+}
+
+// 按展示格式写出一个标签: 点、反斜杠和区域文件中的特殊字符前加反斜杠,
+// 不可打印的字节和非ASCII字节写成 \DDD
+fn escape_label(label: &[u8], out: &mut String) {
+    for &b in label {
+        match b {
+            b'.' | b'\\' | b'"' | b'(' | b')' | b';' | b'@' | b'$' => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            0x21..=0x7E => out.push(b as char),
+            _ => out.push_str(&format!("\\{:03}", b)),
+        }
+    }
+}
+
+// 输出为可以再被 FromStr 解析的形式, A-label 保持 `xn--` 形式
+impl fmt::Display for DnsName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_root() {
+            return write!(f, ".");
+        }
+        let mut out = String::new();
+        for (i, label) in self.labels.iter().enumerate() {
+            if i > 0 {
+                out.push('.');
+            }
+            escape_label(label, &mut out);
+        }
+        write!(f, "{}", out)
+    }
+}
+
+// 日志中直接显示成字符串, 不显示成字节数组; 国际化域名同时显示 Unicode 形式
+impl fmt::Debug for DnsName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.unicode_hint() {
+            Some(unicode) => write!(f, "{:?} ({})", self.to_string(), unicode),
+            None => write!(f, "{:?}", self.to_string()),
+        }
+    }
 }
 
 // # DnsQuestion
 // 枚举使我们以后可以轻松添加更多记录类型。现在输入问题：
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DnsQuestion {
-    pub name: String,
+    pub name: DnsName,
     pub qtype: QueryType,
+    pub qclass: QueryClass,
 }
 impl DnsQuestion {
-    pub fn new(name: String, qtype: QueryType) -> DnsQuestion {
+    pub fn new(name: DnsName, qtype: QueryType) -> DnsQuestion {
+        DnsQuestion::with_class(name, qtype, QueryClass::IN)
+    }
+
+    pub fn with_class(name: DnsName, qtype: QueryType, qclass: QueryClass) -> DnsQuestion {
         DnsQuestion {
-            name: name,
-            qtype: qtype,
+            name,
+            qtype,
+            qclass,
         }
     }
     // 作为BytePacketBuffer结构的一部分，完成了读取域名的艰苦工作后，事实证明它非常紧凑。
-    pub fn read(&mut self,buffer: &mut BytePacketBuffer) -> Result<(),Box<dyn Error>> {
-        buffer.read_qname(&mut self.name)?;
+    pub fn read(&mut self,buffer: &mut BytePacketBuffer) -> Result<(),DnsError> {
+        self.name = buffer.read_name()?;
         self.qtype = QueryType::from_num(buffer.read_u16()?);
-        let _ = buffer.read_u16()?;
+        self.qclass = QueryClass::from_num(buffer.read_u16()?);
 
         Ok(())
     }
 
-    pub fn write(&self,buffer: &mut BytePacketBuffer) -> Result<(),Box<dyn Error>> {
-        buffer.write_qname(&self.name)?;
+    pub fn write(&self,buffer: &mut BytePacketBuffer) -> Result<(),DnsError> {
+        buffer.write_name(&self.name)?;
 
         let typenum = self.qtype.to_num();
         buffer.write_u16(typenum)?;
-        buffer.write_u16(1)?;
+        buffer.write_u16(self.qclass.to_num())?;
 
         Ok(())
     }
 }
 
+// CAA 的 tag 由1到15个ASCII字母和数字组成 (RFC 8659 4.1)
+pub fn valid_caa_tag(tag: &[u8]) -> bool {
+    (1..=15).contains(&tag.len()) && tag.iter().all(u8::is_ascii_alphanumeric)
+}
+
+// # SvcParam
+// SVCB/HTTPS 记录中的服务参数 (RFC 9460)
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SvcParam {
+    Mandatory(Vec<u16>),      // 0
+    Alpn(Vec<Vec<u8>>),       // 1
+    NoDefaultAlpn,            // 2
+    Port(u16),                // 3
+    Ipv4Hint(Vec<Ipv4Addr>),  // 4
+    Ech(Vec<u8>),             // 5
+    Ipv6Hint(Vec<Ipv6Addr>),  // 6
+    Unknown(u16, Vec<u8>),    // 其他 key, 原样保存
+}
+
+impl SvcParam {
+    pub fn key(&self) -> u16 {
+        match *self {
+            SvcParam::Mandatory(_) => 0,
+            SvcParam::Alpn(_) => 1,
+            SvcParam::NoDefaultAlpn => 2,
+            SvcParam::Port(_) => 3,
+            SvcParam::Ipv4Hint(_) => 4,
+            SvcParam::Ech(_) => 5,
+            SvcParam::Ipv6Hint(_) => 6,
+            SvcParam::Unknown(key, _) => key,
+        }
+    }
+
+    // 参数名的表示形式, 未知的 key 写作 keyNNNNN
+    pub fn key_name(key: u16) -> String {
+        match key {
+            0 => "mandatory".to_string(),
+            1 => "alpn".to_string(),
+            2 => "no-default-alpn".to_string(),
+            3 => "port".to_string(),
+            4 => "ipv4hint".to_string(),
+            5 => "ech".to_string(),
+            6 => "ipv6hint".to_string(),
+            _ => format!("key{}", key),
+        }
+    }
+
+    // 从 key 和原始的 value 解析参数, 长度不符时报错
+    fn from_wire(key: u16, value: Vec<u8>) -> Result<SvcParam,DnsError> {
+        let param = match key {
+            0 if value.len().is_multiple_of(2) => SvcParam::Mandatory(
+                value.chunks(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect(),
+            ),
+            // 至少一个协议名, 每个协议名都不能为空
+            1 if !value.is_empty() => {
+                let mut ids = Vec::new();
+                let mut rest = &value[..];
+                while let Some((&len, tail)) = rest.split_first() {
+                    if len == 0 || tail.len() < len as usize {
+                        return Err(DnsError::Malformed("alpn SvcParam".to_string()));
+                    }
+                    let (id, tail) = tail.split_at(len as usize);
+                    ids.push(id.to_vec());
+                    rest = tail;
+                }
+                SvcParam::Alpn(ids)
+            }
+            2 if value.is_empty() => SvcParam::NoDefaultAlpn,
+            3 if value.len() == 2 => SvcParam::Port(u16::from_be_bytes([value[0], value[1]])),
+            4 if value.len().is_multiple_of(4) => SvcParam::Ipv4Hint(
+                value.chunks(4).map(|c| Ipv4Addr::new(c[0], c[1], c[2], c[3])).collect(),
+            ),
+            5 => SvcParam::Ech(value),
+            6 if value.len().is_multiple_of(16) => SvcParam::Ipv6Hint(
+                value.chunks(16).map(|c| {
+                    let mut octets = [0u8; 16];
+                    octets.copy_from_slice(c);
+                    Ipv6Addr::from(octets)
+                }).collect(),
+            ),
+            0..=6 => return Err(DnsError::Malformed(format!("{} SvcParam", SvcParam::key_name(key)))),
+            _ => SvcParam::Unknown(key, value),
+        };
+
+        Ok(param)
+    }
+
+    fn to_wire(&self) -> Result<Vec<u8>,DnsError> {
+        let mut value = Vec::new();
+        match *self {
+            SvcParam::Mandatory(ref keys) => {
+                for key in keys {
+                    value.extend_from_slice(&key.to_be_bytes());
+                }
+            }
+            SvcParam::Alpn(ref ids) => {
+                if ids.is_empty() {
+                    return Err(DnsError::InvalidData("empty alpn SvcParam".to_string()));
+                }
+                for id in ids {
+                    if id.is_empty() || id.len() > 255 {
+                        return Err(DnsError::InvalidData("alpn id length".to_string()));
+                    }
+                    value.push(id.len() as u8);
+                    value.extend_from_slice(id);
+                }
+            }
+            SvcParam::NoDefaultAlpn => {}
+            SvcParam::Port(port) => value.extend_from_slice(&port.to_be_bytes()),
+            SvcParam::Ipv4Hint(ref addrs) => {
+                for addr in addrs {
+                    value.extend_from_slice(&addr.octets());
+                }
+            }
+            SvcParam::Ech(ref data) | SvcParam::Unknown(_, ref data) => value.extend_from_slice(data),
+            SvcParam::Ipv6Hint(ref addrs) => {
+                for addr in addrs {
+                    value.extend_from_slice(&addr.octets());
+                }
+            }
+        }
+
+        Ok(value)
+    }
+}
+
+// 表示形式, 例如 alpn=h2,h3  port=443  ech=AEn+DQBF...
+impl fmt::Display for SvcParam {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", SvcParam::key_name(self.key()))?;
+        match *self {
+            SvcParam::Mandatory(ref keys) => {
+                let names: Vec<String> = keys.iter().map(|k| SvcParam::key_name(*k)).collect();
+                write!(f, "={}", names.join(","))
+            }
+            SvcParam::Alpn(ref ids) => {
+                // 协议名中的逗号和反斜杠需要转义 (RFC 9460 A.1), 不可打印的字节使用 \DDD 转义
+                write!(f, "=")?;
+                for (i, id) in ids.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    for &b in id {
+                        match b {
+                            b',' | b'\\' | b'"' | b';' | b'(' | b')' => write!(f, "\\{}", b as char)?,
+                            0x21..=0x7E => write!(f, "{}", b as char)?,
+                            _ => write!(f, "\\{:03}", b)?,
+                        }
+                    }
+                }
+                Ok(())
+            }
+            SvcParam::NoDefaultAlpn => Ok(()),
+            SvcParam::Port(port) => write!(f, "={}", port),
+            SvcParam::Ipv4Hint(ref addrs) => {
+                let addrs: Vec<String> = addrs.iter().map(|a| a.to_string()).collect();
+                write!(f, "={}", addrs.join(","))
+            }
+            SvcParam::Ech(ref data) => write!(f, "={}", base64_encode(data)),
+            SvcParam::Ipv6Hint(ref addrs) => {
+                let addrs: Vec<String> = addrs.iter().map(|a| a.to_string()).collect();
+                write!(f, "={}", addrs.join(","))
+            }
+            SvcParam::Unknown(_, ref data) => {
+                // 不可打印的字节使用 \DDD 转义
+                write!(f, "=\"")?;
+                for &b in data {
+                    match b {
+                        b'"' | b'\\' => write!(f, "\\{}", b as char)?,
+                        0x21..=0x7E => write!(f, "{}", b as char)?,
+                        _ => write!(f, "\\{:03}", b)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+        }
+    }
+}
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// 标准 base64 编码 (带填充), 用于在表示形式中显示二进制数据
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | (b[2] as u32);
+        out.push(BASE64_CHARS[(n >> 18) as usize & 0x3F] as char);
+        out.push(BASE64_CHARS[(n >> 12) as usize & 0x3F] as char);
+        out.push(if chunk.len() > 1 { BASE64_CHARS[(n >> 6) as usize & 0x3F] as char } else { '=' });
+        out.push(if chunk.len() > 2 { BASE64_CHARS[n as usize & 0x3F] as char } else { '=' });
+    }
+    out
+}
+
+// base64 解码, 忽略空白; 格式不对时返回 None
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    for (i, chunk) in text.chunks(4).enumerate() {
+        let last = i == text.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&b| b == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for &b in &chunk[..4 - padding] {
+            let value = BASE64_CHARS.iter().position(|&c| c == b)? as u32;
+            n = (n << 6) | value;
+        }
+        n <<= 6 * padding as u32;
+        let bytes = [(n >> 16) as u8, (n >> 8) as u8, n as u8];
+        out.extend_from_slice(&bytes[..3 - padding]);
+    }
+    Some(out)
+}
+
+// # Type bitmap
+// NSEC/NSEC3 中用窗口块表示的类型列表 (RFC 4034 4.1.2)。
+// 只接受规范形式: 窗口号严格递增, 每块1到32字节且最后一个字节不为0。
+// 否则写回时会得到不同的 RDATA, 签名就无法验证了
+fn read_type_bitmap(data: &[u8]) -> Result<Vec<u16>,DnsError> {
+    let mut types = Vec::new();
+    let mut rest = data;
+    let mut last_window = None;
+    while !rest.is_empty() {
+        if rest.len() < 2 {
+            return Err(DnsError::Malformed("type bitmap".to_string()));
+        }
+        let window = rest[0] as u16;
+        let len = rest[1] as usize;
+        if len == 0 || len > 32 || rest.len() < 2 + len {
+            return Err(DnsError::Malformed("type bitmap".to_string()));
+        }
+        if last_window.is_some_and(|last| window <= last) {
+            return Err(DnsError::Malformed("type bitmap windows out of order".to_string()));
+        }
+        if rest[1 + len] == 0 {
+            return Err(DnsError::Malformed("type bitmap has trailing zero octets".to_string()));
+        }
+        last_window = Some(window);
+        for (i, byte) in rest[2..2 + len].iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    types.push((window << 8) | (i as u16 * 8 + bit));
+                }
+            }
+        }
+        rest = &rest[2 + len..];
+    }
+
+    Ok(types)
+}
+
+fn write_type_bitmap(types: &[u16]) -> Vec<u8> {
+    let mut types = types.to_vec();
+    types.sort_unstable();
+    types.dedup();
+
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < types.len() {
+        let window = types[i] >> 8;
+        let mut bitmap = [0u8; 32];
+        let mut len = 0;
+        while i < types.len() && types[i] >> 8 == window {
+            let low = (types[i] & 0xFF) as usize;
+            bitmap[low / 8] |= 0x80 >> (low % 8);
+            len = low / 8 + 1;
+            i += 1;
+        }
+        out.push(window as u8);
+        out.push(len as u8);
+        out.extend_from_slice(&bitmap[..len]);
+    }
+
+    out
+}
+
 // # DnsRecord
 // 显然，我们还将需要一种表示实际dns记录的方式，并且我们将再次使用一个枚举来轻松扩展：
 #[derive(Debug,Clone,Eq, PartialEq,Hash,PartialOrd,Ord)]
 pub enum DnsRecord {
     UNKNOWN {
-        domain: String,
+        domain: DnsName,
+        class: QueryClass,
         qtype: u16,
-        data_len: u16,
+        data: Vec<u8>, // 原样保存的 RDATA (RFC 3597), 转发时逐字节写回
         ttl: u32,
     }, // 0
     A {
-        domain: String,
+        domain: DnsName,
+        class: QueryClass,
         addr: Ipv4Addr,
         ttl: u32,
     }, // 1
     NS {
-        domain: String,
-        host: String,
+        domain: DnsName,
+        class: QueryClass,
+        host: DnsName,
         ttl: u32,
     }, // 2
     CNAME {
-        domain: String,
-        host: String,
+        domain: DnsName,
+        class: QueryClass,
+        host: DnsName,
         ttl: u32,
     }, // 5
+    SOA {
+        domain: DnsName,
+        class: QueryClass,
+        mname: DnsName, // 主服务器
+        rname: DnsName, // 管理员邮箱
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        minimum: u32, // 否定应答的缓存时间 (RFC 2308)
+        ttl: u32,
+    }, // 6
+    PTR {
+        domain: DnsName,
+        class: QueryClass,
+        host: DnsName,
+        ttl: u32,
+    }, // 12
     MX {
-        domain: String,
+        domain: DnsName,
+        class: QueryClass,
         priority: u16,
-        host: String,
+        host: DnsName,
         ttl: u32,
     }, // 15
+    TXT {
+        domain: DnsName,
+        class: QueryClass,
+        data: Vec<Vec<u8>>, // 一个或多个 <character-string>, 每个最长255字节
+        ttl: u32,
+    }, // 16
     AAAA {
-        domain: String,
+        domain: DnsName,
+        class: QueryClass,
         addr: Ipv6Addr,
         ttl: u32,
     }, // 28
-}
-// 由于记录的类型很多，我们将添加跟踪尚未遇到的记录类型的功能。
-// 枚举还使我们以后可以轻松添加新记录。 DnsRecord的实际实现如下所示：
+    SRV {
+        domain: DnsName,
+        class: QueryClass,
+        priority: u16,
+        weight: u16,
+        port: u16,
+        host: DnsName,
+        ttl: u32,
+    }, // 33
+    NAPTR {
+        domain: DnsName,
+        class: QueryClass,
+        order: u16,
+        preference: u16,
+        flags: Vec<u8>,    // 三个字段都是 <character-string>, 按原始字节保存
+        services: Vec<u8>,
+        regexp: Vec<u8>,
+        replacement: DnsName,
+        ttl: u32,
+    }, // 35
+    DS {
+        domain: DnsName,
+        class: QueryClass,
+        key_tag: u16,
+        algorithm: u8,
+        digest_type: u8,
+        digest: Vec<u8>,
+        ttl: u32,
+    }, // 43
+    SSHFP {
+        domain: DnsName,
+        class: QueryClass,
+        algorithm: u8, // 1 RSA, 2 DSA, 3 ECDSA, 4 Ed25519
+        fp_type: u8,   // 1 SHA-1, 2 SHA-256
+        fingerprint: Vec<u8>,
+        ttl: u32,
+    }, // 44
+    RRSIG {
+        domain: DnsName,
+        class: QueryClass,
+        type_covered: u16,
+        algorithm: u8,
+        labels: u8,
+        original_ttl: u32,
+        expiration: u32, // 自1970年以来的秒数 (模 2^32)
+        inception: u32,
+        key_tag: u16,
+        signer_name: DnsName,
+        signature: Vec<u8>,
+        ttl: u32,
+    }, // 46
+    NSEC {
+        domain: DnsName,
+        class: QueryClass,
+        next_domain: DnsName,
+        types: Vec<u16>, // 该名称下存在的记录类型
+        ttl: u32,
+    }, // 47
+    DNSKEY {
+        domain: DnsName,
+        class: QueryClass,
+        flags: u16, // 256 ZSK, 257 KSK
+        protocol: u8, // 总是 3
+        algorithm: u8,
+        public_key: Vec<u8>,
+        ttl: u32,
+    }, // 48
+    NSEC3 {
+        domain: DnsName,
+        class: QueryClass,
+        hash_algorithm: u8,
+        flags: u8, // 最低位为 opt-out
+        iterations: u16,
+        salt: Vec<u8>,
+        next_hashed: Vec<u8>, // 下一个名称的哈希 (未经 base32 编码)
+        types: Vec<u16>,
+        ttl: u32,
+    }, // 50
+    NSEC3PARAM {
+        domain: DnsName,
+        class: QueryClass,
+        hash_algorithm: u8,
+        flags: u8,
+        iterations: u16,
+        salt: Vec<u8>,
+        ttl: u32,
+    }, // 51
+    TLSA {
+        domain: DnsName,
+        class: QueryClass,
+        usage: u8,
+        selector: u8,
+        matching_type: u8,
+        data: Vec<u8>, // 证书关联数据
+        ttl: u32,
+    }, // 52
+    SVCB {
+        domain: DnsName,
+        class: QueryClass,
+        priority: u16, // 0 表示别名模式
+        target: DnsName,
+        params: Vec<SvcParam>,
+        ttl: u32,
+    }, // 64
+    HTTPS {
+        domain: DnsName,
+        class: QueryClass,
+        priority: u16,
+        target: DnsName,
+        params: Vec<SvcParam>,
+        ttl: u32,
+    }, // 65
+    CAA {
+        domain: DnsName,
+        class: QueryClass,
+        flags: u8, // 最高位为 issuer critical
+        tag: Vec<u8>, // issue, issuewild, iodef ... 只能是字母和数字
+        value: Vec<u8>,
+        ttl: u32,
+    }, // 257
+}
+// 由于记录的类型很多，我们将添加跟踪尚未遇到的记录类型的功能。
+// 枚举还使我们以后可以轻松添加新记录。 DnsRecord的实际实现如下所示：
 impl DnsRecord {
-    pub fn read(buffer: &mut BytePacketBuffer) -> Result<DnsRecord,Box<dyn Error>> {
-        let mut domain = String::new();
-        buffer.read_qname(&mut domain)?;
+    // 只有客户端设置了DO位才需要返回的 DNSSEC 证明记录
+    fn dnssec_type(&self) -> Option<QueryType> {
+        match *self {
+            DnsRecord::RRSIG { .. } => Some(QueryType::RRSIG),
+            DnsRecord::NSEC { .. } => Some(QueryType::NSEC),
+            DnsRecord::NSEC3 { .. } => Some(QueryType::NSEC3),
+            _ => None,
+        }
+    }
+
+    pub fn qtype(&self) -> QueryType {
+        match *self {
+            DnsRecord::UNKNOWN { qtype, .. } => QueryType::from_num(qtype),
+            DnsRecord::A { .. } => QueryType::A,
+            DnsRecord::NS { .. } => QueryType::NS,
+            DnsRecord::CNAME { .. } => QueryType::CNAME,
+            DnsRecord::SOA { .. } => QueryType::SOA,
+            DnsRecord::PTR { .. } => QueryType::PTR,
+            DnsRecord::MX { .. } => QueryType::MX,
+            DnsRecord::TXT { .. } => QueryType::TXT,
+            DnsRecord::AAAA { .. } => QueryType::AAAA,
+            DnsRecord::SRV { .. } => QueryType::SRV,
+            DnsRecord::NAPTR { .. } => QueryType::NAPTR,
+            DnsRecord::DS { .. } => QueryType::DS,
+            DnsRecord::SSHFP { .. } => QueryType::SSHFP,
+            DnsRecord::RRSIG { .. } => QueryType::RRSIG,
+            DnsRecord::NSEC { .. } => QueryType::NSEC,
+            DnsRecord::DNSKEY { .. } => QueryType::DNSKEY,
+            DnsRecord::NSEC3 { .. } => QueryType::NSEC3,
+            DnsRecord::NSEC3PARAM { .. } => QueryType::NSEC3PARAM,
+            DnsRecord::TLSA { .. } => QueryType::TLSA,
+            DnsRecord::SVCB { .. } => QueryType::SVCB,
+            DnsRecord::HTTPS { .. } => QueryType::HTTPS,
+            DnsRecord::CAA { .. } => QueryType::CAA,
+        }
+    }
+
+    pub fn class(&self) -> QueryClass {
+        match *self {
+            DnsRecord::UNKNOWN { class, .. }
+            | DnsRecord::A { class, .. }
+            | DnsRecord::NS { class, .. }
+            | DnsRecord::CNAME { class, .. }
+            | DnsRecord::SOA { class, .. }
+            | DnsRecord::PTR { class, .. }
+            | DnsRecord::MX { class, .. }
+            | DnsRecord::TXT { class, .. }
+            | DnsRecord::AAAA { class, .. }
+            | DnsRecord::SRV { class, .. }
+            | DnsRecord::NAPTR { class, .. }
+            | DnsRecord::DS { class, .. }
+            | DnsRecord::SSHFP { class, .. }
+            | DnsRecord::RRSIG { class, .. }
+            | DnsRecord::NSEC { class, .. }
+            | DnsRecord::DNSKEY { class, .. }
+            | DnsRecord::NSEC3 { class, .. }
+            | DnsRecord::NSEC3PARAM { class, .. }
+            | DnsRecord::TLSA { class, .. }
+            | DnsRecord::SVCB { class, .. }
+            | DnsRecord::HTTPS { class, .. }
+            | DnsRecord::CAA { class, .. } => class,
+        }
+    }
+
+    pub fn ttl(&self) -> u32 {
+        match *self {
+            DnsRecord::UNKNOWN { ttl, .. }
+            | DnsRecord::A { ttl, .. }
+            | DnsRecord::NS { ttl, .. }
+            | DnsRecord::CNAME { ttl, .. }
+            | DnsRecord::SOA { ttl, .. }
+            | DnsRecord::PTR { ttl, .. }
+            | DnsRecord::MX { ttl, .. }
+            | DnsRecord::TXT { ttl, .. }
+            | DnsRecord::AAAA { ttl, .. }
+            | DnsRecord::SRV { ttl, .. }
+            | DnsRecord::NAPTR { ttl, .. }
+            | DnsRecord::DS { ttl, .. }
+            | DnsRecord::SSHFP { ttl, .. }
+            | DnsRecord::RRSIG { ttl, .. }
+            | DnsRecord::NSEC { ttl, .. }
+            | DnsRecord::DNSKEY { ttl, .. }
+            | DnsRecord::NSEC3 { ttl, .. }
+            | DnsRecord::NSEC3PARAM { ttl, .. }
+            | DnsRecord::TLSA { ttl, .. }
+            | DnsRecord::SVCB { ttl, .. }
+            | DnsRecord::HTTPS { ttl, .. }
+            | DnsRecord::CAA { ttl, .. } => ttl,
+        }
+    }
+
+    pub fn set_ttl(&mut self, new_ttl: u32) {
+        match *self {
+            DnsRecord::UNKNOWN { ref mut ttl, .. }
+            | DnsRecord::A { ref mut ttl, .. }
+            | DnsRecord::NS { ref mut ttl, .. }
+            | DnsRecord::CNAME { ref mut ttl, .. }
+            | DnsRecord::SOA { ref mut ttl, .. }
+            | DnsRecord::PTR { ref mut ttl, .. }
+            | DnsRecord::MX { ref mut ttl, .. }
+            | DnsRecord::TXT { ref mut ttl, .. }
+            | DnsRecord::AAAA { ref mut ttl, .. }
+            | DnsRecord::SRV { ref mut ttl, .. }
+            | DnsRecord::NAPTR { ref mut ttl, .. }
+            | DnsRecord::DS { ref mut ttl, .. }
+            | DnsRecord::SSHFP { ref mut ttl, .. }
+            | DnsRecord::RRSIG { ref mut ttl, .. }
+            | DnsRecord::NSEC { ref mut ttl, .. }
+            | DnsRecord::DNSKEY { ref mut ttl, .. }
+            | DnsRecord::NSEC3 { ref mut ttl, .. }
+            | DnsRecord::NSEC3PARAM { ref mut ttl, .. }
+            | DnsRecord::TLSA { ref mut ttl, .. }
+            | DnsRecord::SVCB { ref mut ttl, .. }
+            | DnsRecord::HTTPS { ref mut ttl, .. }
+            | DnsRecord::CAA { ref mut ttl, .. } => *ttl = new_ttl,
+        }
+    }
+
+    pub fn domain(&self) -> &DnsName {
+        match *self {
+            DnsRecord::UNKNOWN { ref domain, .. }
+            | DnsRecord::A { ref domain, .. }
+            | DnsRecord::NS { ref domain, .. }
+            | DnsRecord::CNAME { ref domain, .. }
+            | DnsRecord::SOA { ref domain, .. }
+            | DnsRecord::PTR { ref domain, .. }
+            | DnsRecord::MX { ref domain, .. }
+            | DnsRecord::TXT { ref domain, .. }
+            | DnsRecord::AAAA { ref domain, .. }
+            | DnsRecord::SRV { ref domain, .. }
+            | DnsRecord::NAPTR { ref domain, .. }
+            | DnsRecord::DS { ref domain, .. }
+            | DnsRecord::SSHFP { ref domain, .. }
+            | DnsRecord::RRSIG { ref domain, .. }
+            | DnsRecord::NSEC { ref domain, .. }
+            | DnsRecord::DNSKEY { ref domain, .. }
+            | DnsRecord::NSEC3 { ref domain, .. }
+            | DnsRecord::NSEC3PARAM { ref domain, .. }
+            | DnsRecord::TLSA { ref domain, .. }
+            | DnsRecord::SVCB { ref domain, .. }
+            | DnsRecord::HTTPS { ref domain, .. }
+            | DnsRecord::CAA { ref domain, .. } => domain,
+        }
+    }
+
+    pub fn set_domain(&mut self, new_domain: DnsName) {
+        match *self {
+            DnsRecord::UNKNOWN { ref mut domain, .. }
+            | DnsRecord::A { ref mut domain, .. }
+            | DnsRecord::NS { ref mut domain, .. }
+            | DnsRecord::CNAME { ref mut domain, .. }
+            | DnsRecord::SOA { ref mut domain, .. }
+            | DnsRecord::PTR { ref mut domain, .. }
+            | DnsRecord::MX { ref mut domain, .. }
+            | DnsRecord::TXT { ref mut domain, .. }
+            | DnsRecord::AAAA { ref mut domain, .. }
+            | DnsRecord::SRV { ref mut domain, .. }
+            | DnsRecord::NAPTR { ref mut domain, .. }
+            | DnsRecord::DS { ref mut domain, .. }
+            | DnsRecord::SSHFP { ref mut domain, .. }
+            | DnsRecord::RRSIG { ref mut domain, .. }
+            | DnsRecord::NSEC { ref mut domain, .. }
+            | DnsRecord::DNSKEY { ref mut domain, .. }
+            | DnsRecord::NSEC3 { ref mut domain, .. }
+            | DnsRecord::NSEC3PARAM { ref mut domain, .. }
+            | DnsRecord::TLSA { ref mut domain, .. }
+            | DnsRecord::SVCB { ref mut domain, .. }
+            | DnsRecord::HTTPS { ref mut domain, .. }
+            | DnsRecord::CAA { ref mut domain, .. } => *domain = new_domain,
+        }
+    }
+
+    // 记录中出现的所有域名: 所有者以及 RDATA 中的域名
+    fn names(&self) -> Vec<&DnsName> {
+        let mut names = vec![self.domain()];
+        match *self {
+            DnsRecord::NS { ref host, .. }
+            | DnsRecord::CNAME { ref host, .. }
+            | DnsRecord::PTR { ref host, .. }
+            | DnsRecord::MX { ref host, .. }
+            | DnsRecord::SRV { ref host, .. } => names.push(host),
+            DnsRecord::SOA { ref mname, ref rname, .. } => names.extend([mname, rname]),
+            DnsRecord::NAPTR { ref replacement, .. } => names.push(replacement),
+            DnsRecord::RRSIG { ref signer_name, .. } => names.push(signer_name),
+            DnsRecord::NSEC { ref next_domain, .. } => names.push(next_domain),
+            DnsRecord::SVCB { ref target, .. } | DnsRecord::HTTPS { ref target, .. } => names.push(target),
+            _ => {}
+        }
+        names
+    }
+
+    // 与 `==` 相同, 但其中的域名还必须大小写一致
+    pub fn eq_exact(&self, other: &DnsRecord) -> bool {
+        self == other && self.names().iter().zip(other.names()).all(|(a, b)| a.eq_exact(b))
+    }
+
+    pub fn read(buffer: &mut BytePacketBuffer) -> Result<DnsRecord,DnsError> {
+        let domain = buffer.read_name()?;
 
         let qtype_num = buffer.read_u16()?;
         let qtype = QueryType::from_num(qtype_num);
-        let _ = buffer.read_u16()?;
+        let class = QueryClass::from_num(buffer.read_u16()?);
         let ttl = buffer.read_u32()?;
         let data_len = buffer.read_u16()?;
+        let rdata_start = buffer.pos();
 
-        match qtype {
+        match DnsRecord::read_rdata(buffer, domain.clone(), class, qtype_num, ttl, data_len) {
+            Ok(record) => Ok(record),
+            // RDATA 中不含压缩域名的类型, 内容不合法但 RDLENGTH 没有越界时原样保留为 UNKNOWN,
+            // 不能因为一条记录让整个上游应答解析失败 (RFC 3597)
+            Err(e) if qtype.is_opaque() && e.is_format_error() => {
+                buffer.seek(rdata_start)?;
+                let data = buffer.read_bytes(data_len as usize)?;
+
+                Ok(DnsRecord::UNKNOWN {
+                    domain,
+                    class,
+                    qtype: qtype_num,
+                    data,
+                    ttl,
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // 按类型解析 RDATA, 缓冲区位于 RDLENGTH 之后
+    fn read_rdata(buffer: &mut BytePacketBuffer, domain: DnsName, class: QueryClass, qtype_num: u16, ttl: u32, data_len: u16) -> Result<DnsRecord,DnsError> {
+        let qtype = QueryType::from_num(qtype_num);
+        let rdata_start = buffer.pos();
+
+        let record: Result<DnsRecord,DnsError> = match qtype {
             QueryType::A => {
                 let raw_addr = buffer.read_u32()?;
                 let addr = Ipv4Addr::new(
                     ((raw_addr >> 24) & 0xFF) as u8,
                     ((raw_addr >> 16) & 0xFF) as u8,
                     ((raw_addr >> 8) & 0xFF) as u8,
-                    ((raw_addr >> 0) & 0xFF) as u8,
+                    (raw_addr & 0xFF) as u8,
                 );
 
                 Ok(DnsRecord::A {
-                    domain: domain,
-                    addr: addr,
-                    ttl: ttl,
+                    domain,
+                    class,
+                    addr,
+                    ttl,
                 })
             }
             QueryType::AAAA => {
@@ -503,78 +1823,352 @@ impl DnsRecord {
                 let raw_addr4 = buffer.read_u32()?;
                 let addr = Ipv6Addr::new(
                     ((raw_addr1 >> 16) & 0xFFFF) as u16,
-                    ((raw_addr1 >> 0) & 0xFFFF) as u16,
+                    (raw_addr1 & 0xFFFF) as u16,
                     ((raw_addr2 >> 16) & 0xFFFF) as u16,
-                    ((raw_addr2 >> 0) & 0xFFFF) as u16,
+                    (raw_addr2 & 0xFFFF) as u16,
                     ((raw_addr3 >> 16) & 0xFFFF) as u16,
-                    ((raw_addr3 >> 0) & 0xFFFF) as u16,
+                    (raw_addr3 & 0xFFFF) as u16,
                     ((raw_addr4 >> 16) & 0xFFFF) as u16,
-                    ((raw_addr4 >> 0) & 0xFFFF) as u16,
+                    (raw_addr4 & 0xFFFF) as u16,
                 );
 
                 Ok(DnsRecord::AAAA {
                     domain,
+                    class,
                     addr,
                     ttl,
                 })
             }
             QueryType::NS => {
-                let mut ns = String::new();
-                buffer.read_qname(&mut ns)?;
+                let ns = buffer.read_name()?;
 
                 Ok(DnsRecord::NS {
                     domain,
+                    class,
                     host: ns,
                     ttl,
                 })
             }
             QueryType::CNAME => {
-                let mut cname = String::new();
-                buffer.read_qname(&mut cname)?;
+                let cname = buffer.read_name()?;
 
                 Ok(DnsRecord::CNAME {
                     domain,
+                    class,
                     host: cname,
                     ttl,
                 })
             }
             QueryType::MX => {
                 let priority = buffer.read_u16()?;
-                let mut mx = String::new();
-                buffer.read_qname(&mut mx)?;
+                let mx = buffer.read_name()?;
 
                 Ok(DnsRecord::MX {
                     domain,
+                    class,
                     priority,
                     host: mx,
                     ttl,
                 })
             }
-            QueryType::UNKNOWN(_) => {
-                buffer.step(data_len as usize)?;
+            QueryType::PTR => {
+                let ptr = buffer.read_name()?;
+
+                Ok(DnsRecord::PTR {
+                    domain,
+                    class,
+                    host: ptr,
+                    ttl,
+                })
+            }
+            QueryType::TXT => {
+                let end = buffer.pos() + data_len as usize;
+                let mut data = Vec::new();
+                while buffer.pos() < end {
+                    data.push(buffer.read_character_string()?);
+                }
+
+                Ok(DnsRecord::TXT {
+                    domain,
+                    class,
+                    data,
+                    ttl,
+                })
+            }
+            QueryType::SRV => {
+                let priority = buffer.read_u16()?;
+                let weight = buffer.read_u16()?;
+                let port = buffer.read_u16()?;
+                let srv = buffer.read_name()?;
+
+                Ok(DnsRecord::SRV {
+                    domain,
+                    class,
+                    priority,
+                    weight,
+                    port,
+                    host: srv,
+                    ttl,
+                })
+            }
+            QueryType::NAPTR => {
+                let order = buffer.read_u16()?;
+                let preference = buffer.read_u16()?;
+                let flags = buffer.read_character_string()?;
+                let services = buffer.read_character_string()?;
+                let regexp = buffer.read_character_string()?;
+                let replacement = buffer.read_name()?;
+
+                Ok(DnsRecord::NAPTR {
+                    domain,
+                    class,
+                    order,
+                    preference,
+                    flags,
+                    services,
+                    regexp,
+                    replacement,
+                    ttl,
+                })
+            }
+            QueryType::DS => {
+                let end = buffer.pos() + data_len as usize;
+                let key_tag = buffer.read_u16()?;
+                let algorithm = buffer.read()?;
+                let digest_type = buffer.read()?;
+                let digest = buffer.read_bytes(end.saturating_sub(buffer.pos()))?;
+
+                Ok(DnsRecord::DS {
+                    domain,
+                    class,
+                    key_tag,
+                    algorithm,
+                    digest_type,
+                    digest,
+                    ttl,
+                })
+            }
+            QueryType::RRSIG => {
+                let end = buffer.pos() + data_len as usize;
+                let type_covered = buffer.read_u16()?;
+                let algorithm = buffer.read()?;
+                let labels = buffer.read()?;
+                let original_ttl = buffer.read_u32()?;
+                let expiration = buffer.read_u32()?;
+                let inception = buffer.read_u32()?;
+                let key_tag = buffer.read_u16()?;
+                let signer_name = buffer.read_name()?;
+                let signature = buffer.read_bytes(end.saturating_sub(buffer.pos()))?;
+
+                Ok(DnsRecord::RRSIG {
+                    domain,
+                    class,
+                    type_covered,
+                    algorithm,
+                    labels,
+                    original_ttl,
+                    expiration,
+                    inception,
+                    key_tag,
+                    signer_name,
+                    signature,
+                    ttl,
+                })
+            }
+            QueryType::NSEC => {
+                let end = buffer.pos() + data_len as usize;
+                let next_domain = buffer.read_name()?;
+                let types = read_type_bitmap(&buffer.read_bytes(end.saturating_sub(buffer.pos()))?)?;
+
+                Ok(DnsRecord::NSEC {
+                    domain,
+                    class,
+                    next_domain,
+                    types,
+                    ttl,
+                })
+            }
+            QueryType::DNSKEY => {
+                let end = buffer.pos() + data_len as usize;
+                let flags = buffer.read_u16()?;
+                let protocol = buffer.read()?;
+                let algorithm = buffer.read()?;
+                let public_key = buffer.read_bytes(end.saturating_sub(buffer.pos()))?;
+
+                Ok(DnsRecord::DNSKEY {
+                    domain,
+                    class,
+                    flags,
+                    protocol,
+                    algorithm,
+                    public_key,
+                    ttl,
+                })
+            }
+            QueryType::NSEC3 => {
+                let end = buffer.pos() + data_len as usize;
+                let hash_algorithm = buffer.read()?;
+                let flags = buffer.read()?;
+                let iterations = buffer.read_u16()?;
+                let salt = buffer.read_character_string()?;
+                let next_hashed = buffer.read_character_string()?;
+                let types = read_type_bitmap(&buffer.read_bytes(end.saturating_sub(buffer.pos()))?)?;
+
+                Ok(DnsRecord::NSEC3 {
+                    domain,
+                    class,
+                    hash_algorithm,
+                    flags,
+                    iterations,
+                    salt,
+                    next_hashed,
+                    types,
+                    ttl,
+                })
+            }
+            QueryType::NSEC3PARAM => {
+                let hash_algorithm = buffer.read()?;
+                let flags = buffer.read()?;
+                let iterations = buffer.read_u16()?;
+                let salt = buffer.read_character_string()?;
+
+                Ok(DnsRecord::NSEC3PARAM {
+                    domain,
+                    class,
+                    hash_algorithm,
+                    flags,
+                    iterations,
+                    salt,
+                    ttl,
+                })
+            }
+            QueryType::SSHFP => {
+                let end = buffer.pos() + data_len as usize;
+                let algorithm = buffer.read()?;
+                let fp_type = buffer.read()?;
+                let fingerprint = buffer.read_bytes(end.saturating_sub(buffer.pos()))?;
+
+                Ok(DnsRecord::SSHFP {
+                    domain,
+                    class,
+                    algorithm,
+                    fp_type,
+                    fingerprint,
+                    ttl,
+                })
+            }
+            QueryType::TLSA => {
+                let end = buffer.pos() + data_len as usize;
+                let usage = buffer.read()?;
+                let selector = buffer.read()?;
+                let matching_type = buffer.read()?;
+                let data = buffer.read_bytes(end.saturating_sub(buffer.pos()))?;
+
+                Ok(DnsRecord::TLSA {
+                    domain,
+                    class,
+                    usage,
+                    selector,
+                    matching_type,
+                    data,
+                    ttl,
+                })
+            }
+            QueryType::CAA => {
+                let end = buffer.pos() + data_len as usize;
+                let flags = buffer.read()?;
+                let tag = buffer.read_character_string()?;
+                if !valid_caa_tag(&tag) {
+                    return Err(DnsError::Malformed("CAA tag".to_string()));
+                }
+                let value = buffer.read_bytes(end.saturating_sub(buffer.pos()))?;
+
+                Ok(DnsRecord::CAA {
+                    domain,
+                    class,
+                    flags,
+                    tag,
+                    value,
+                    ttl,
+                })
+            }
+            QueryType::SVCB | QueryType::HTTPS => {
+                let end = buffer.pos() + data_len as usize;
+                let priority = buffer.read_u16()?;
+                let target = buffer.read_name()?;
+
+                let mut params: Vec<SvcParam> = Vec::new();
+                while buffer.pos() < end {
+                    let key = buffer.read_u16()?;
+                    // key 必须严格递增, 乱序或重复的参数使整条记录无效 (RFC 9460 2.2)
+                    if params.last().is_some_and(|last| key <= last.key()) {
+                        return Err(DnsError::Malformed("SvcParam keys out of order".to_string()));
+                    }
+                    let len = buffer.read_u16()? as usize;
+                    let value = buffer.read_bytes(len)?;
+                    params.push(SvcParam::from_wire(key, value)?);
+                }
+
+                if qtype == QueryType::SVCB {
+                    Ok(DnsRecord::SVCB { domain, class, priority, target, params, ttl })
+                } else {
+                    Ok(DnsRecord::HTTPS { domain, class, priority, target, params, ttl })
+                }
+            }
+            QueryType::SOA => {
+                let mname = buffer.read_name()?;
+                let rname = buffer.read_name()?;
+
+                Ok(DnsRecord::SOA {
+                    domain,
+                    class,
+                    mname,
+                    rname,
+                    serial: buffer.read_u32()?,
+                    refresh: buffer.read_u32()?,
+                    retry: buffer.read_u32()?,
+                    expire: buffer.read_u32()?,
+                    minimum: buffer.read_u32()?,
+                    ttl,
+                })
+            }
+            // OPT 只应出现在附加部分, 由 DnsPacket::from_buffer 单独处理
+            QueryType::UNKNOWN(_) | QueryType::OPT => {
+                let data = buffer.read_bytes(data_len as usize)?;
 
                 Ok(DnsRecord::UNKNOWN {
                     domain,
+                    class,
                     qtype: qtype_num,
-                    data_len,
+                    data,
                     ttl,
                 })
             }
+        };
+        let record = record?;
+
+        // 解析出的内容必须恰好占满 RDLENGTH, 多读或少读都说明记录是畸形的,
+        // 继续解析只会把后面的字节当成下一条记录
+        if buffer.pos() != rdata_start + data_len as usize {
+            return Err(DnsError::BadRdataLength(qtype_num));
         }
+
+        Ok(record)
     }
     // DnsRecord现在也非常紧凑，尽管我们最终将在此处添加很多代码来处理不同的记录类型
-    pub fn write(&self, buffer: &mut BytePacketBuffer) -> Result<usize,Box<dyn Error>> {
+    pub fn write(&self, buffer: &mut BytePacketBuffer) -> Result<usize,DnsError> {
         let start_pos = buffer.pos();
 
         match *self {
             DnsRecord::A {
                 ref domain,
+                class,
                 ref addr,
                 ttl,
             } => {
-                buffer.write_qname(domain)?;
+                buffer.write_name(domain)?;
                 buffer.write_u16(QueryType::A.to_num())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_num())?;
                 buffer.write_u32(ttl)?;
                 buffer.write_u16(4)?;
 
@@ -586,68 +2180,479 @@ impl DnsRecord {
             }
             DnsRecord::NS {
                 ref domain,
+                class,
                 ref host,
                 ttl,
             } => {
-                buffer.write_qname(domain)?;
+                buffer.write_name(domain)?;
                 buffer.write_u16(QueryType::NS.to_num())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_num())?;
                 buffer.write_u32(ttl)?;
 
                 let pos = buffer.pos();
                 buffer.write_u16(0)?;
 
-                buffer.write_qname(host)?;
+                buffer.write_name(host)?;
 
                 let size = buffer.pos() - (pos + 2);
                 buffer.set_u16(pos, size as u16)?;
             }
             DnsRecord::CNAME {
                 ref domain,
+                class,
                 ref host,
                 ttl,
             } => {
-                buffer.write_qname(domain)?;
+                buffer.write_name(domain)?;
                 buffer.write_u16(QueryType::CNAME.to_num())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_num())?;
                 buffer.write_u32(ttl)?;
 
                 let pos = buffer.pos();
                 buffer.write_u16(0)?;
 
-                buffer.write_qname(host)?;
+                buffer.write_name(host)?;
 
                 let size = buffer.pos() - (pos + 2);
                 buffer.set_u16(pos, size as u16)?;
             }
             DnsRecord::MX {
                 ref domain,
+                class,
                 priority,
                 ref host,
                 ttl,
             } => {
-                buffer.write_qname(domain)?;
+                buffer.write_name(domain)?;
                 buffer.write_u16(QueryType::MX.to_num())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u16(priority)?;
+                buffer.write_name(host)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::PTR {
+                ref domain,
+                class,
+                ref host,
+                ttl,
+            } => {
+                buffer.write_name(domain)?;
+                buffer.write_u16(QueryType::PTR.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_name(host)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::TXT {
+                ref domain,
+                class,
+                ref data,
+                ttl,
+            } => {
+                buffer.write_name(domain)?;
+                buffer.write_u16(QueryType::TXT.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                for s in data {
+                    buffer.write_character_string(s)?;
+                }
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::SRV {
+                ref domain,
+                class,
+                priority,
+                weight,
+                port,
+                ref host,
+                ttl,
+            } => {
+                buffer.write_name(domain)?;
+                buffer.write_u16(QueryType::SRV.to_num())?;
+                buffer.write_u16(class.to_num())?;
                 buffer.write_u32(ttl)?;
 
                 let pos = buffer.pos();
                 buffer.write_u16(0)?;
 
                 buffer.write_u16(priority)?;
-                buffer.write_qname(host)?;
+                buffer.write_u16(weight)?;
+                buffer.write_u16(port)?;
+                buffer.write_name_uncompressed(host)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::NAPTR {
+                ref domain,
+                class,
+                order,
+                preference,
+                ref flags,
+                ref services,
+                ref regexp,
+                ref replacement,
+                ttl,
+            } => {
+                buffer.write_name(domain)?;
+                buffer.write_u16(QueryType::NAPTR.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u16(order)?;
+                buffer.write_u16(preference)?;
+                buffer.write_character_string(flags)?;
+                buffer.write_character_string(services)?;
+                buffer.write_character_string(regexp)?;
+                // RFC 3403 不允许压缩 replacement
+                buffer.write_name_uncompressed(replacement)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::DS {
+                ref domain,
+                class,
+                key_tag,
+                algorithm,
+                digest_type,
+                ref digest,
+                ttl,
+            } => {
+                buffer.write_name(domain)?;
+                buffer.write_u16(QueryType::DS.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u16(key_tag)?;
+                buffer.write_u8(algorithm)?;
+                buffer.write_u8(digest_type)?;
+                buffer.write_bytes(digest)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            // DNSSEC 记录的 RDATA 中的域名一律不压缩, 保持签名验证所需的规范形式 (RFC 4034 6.2)
+            DnsRecord::RRSIG {
+                ref domain,
+                class,
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                ref signer_name,
+                ref signature,
+                ttl,
+            } => {
+                buffer.write_name(domain)?;
+                buffer.write_u16(QueryType::RRSIG.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u16(type_covered)?;
+                buffer.write_u8(algorithm)?;
+                buffer.write_u8(labels)?;
+                buffer.write_u32(original_ttl)?;
+                buffer.write_u32(expiration)?;
+                buffer.write_u32(inception)?;
+                buffer.write_u16(key_tag)?;
+                buffer.write_name_uncompressed(signer_name)?;
+                buffer.write_bytes(signature)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::NSEC {
+                ref domain,
+                class,
+                ref next_domain,
+                ref types,
+                ttl,
+            } => {
+                buffer.write_name(domain)?;
+                buffer.write_u16(QueryType::NSEC.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_name_uncompressed(next_domain)?;
+                buffer.write_bytes(&write_type_bitmap(types))?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::DNSKEY {
+                ref domain,
+                class,
+                flags,
+                protocol,
+                algorithm,
+                ref public_key,
+                ttl,
+            } => {
+                buffer.write_name(domain)?;
+                buffer.write_u16(QueryType::DNSKEY.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u16(flags)?;
+                buffer.write_u8(protocol)?;
+                buffer.write_u8(algorithm)?;
+                buffer.write_bytes(public_key)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::NSEC3 {
+                ref domain,
+                class,
+                hash_algorithm,
+                flags,
+                iterations,
+                ref salt,
+                ref next_hashed,
+                ref types,
+                ttl,
+            } => {
+                buffer.write_name(domain)?;
+                buffer.write_u16(QueryType::NSEC3.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u8(hash_algorithm)?;
+                buffer.write_u8(flags)?;
+                buffer.write_u16(iterations)?;
+                buffer.write_character_string(salt)?;
+                buffer.write_character_string(next_hashed)?;
+                buffer.write_bytes(&write_type_bitmap(types))?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::NSEC3PARAM {
+                ref domain,
+                class,
+                hash_algorithm,
+                flags,
+                iterations,
+                ref salt,
+                ttl,
+            } => {
+                buffer.write_name(domain)?;
+                buffer.write_u16(QueryType::NSEC3PARAM.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u8(hash_algorithm)?;
+                buffer.write_u8(flags)?;
+                buffer.write_u16(iterations)?;
+                buffer.write_character_string(salt)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::SSHFP {
+                ref domain,
+                class,
+                algorithm,
+                fp_type,
+                ref fingerprint,
+                ttl,
+            } => {
+                buffer.write_name(domain)?;
+                buffer.write_u16(QueryType::SSHFP.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u8(algorithm)?;
+                buffer.write_u8(fp_type)?;
+                buffer.write_bytes(fingerprint)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::TLSA {
+                ref domain,
+                class,
+                usage,
+                selector,
+                matching_type,
+                ref data,
+                ttl,
+            } => {
+                buffer.write_name(domain)?;
+                buffer.write_u16(QueryType::TLSA.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u8(usage)?;
+                buffer.write_u8(selector)?;
+                buffer.write_u8(matching_type)?;
+                buffer.write_bytes(data)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::CAA {
+                ref domain,
+                class,
+                flags,
+                ref tag,
+                ref value,
+                ttl,
+            } => {
+                buffer.write_name(domain)?;
+                buffer.write_u16(QueryType::CAA.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u8(flags)?;
+                if !valid_caa_tag(tag) {
+                    return Err(DnsError::InvalidData("CAA tag".to_string()));
+                }
+                buffer.write_character_string(tag)?;
+                buffer.write_bytes(value)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::SVCB {
+                ref domain,
+                class,
+                priority,
+                ref target,
+                ref params,
+                ttl,
+            }
+            | DnsRecord::HTTPS {
+                ref domain,
+                class,
+                priority,
+                ref target,
+                ref params,
+                ttl,
+            } => {
+                let qtype = match *self {
+                    DnsRecord::SVCB { .. } => QueryType::SVCB,
+                    _ => QueryType::HTTPS,
+                };
+                buffer.write_name(domain)?;
+                buffer.write_u16(qtype.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u16(priority)?;
+                buffer.write_name_uncompressed(target)?;
+                // 参数必须按 key 升序排列
+                let mut params: Vec<&SvcParam> = params.iter().collect();
+                params.sort_by_key(|p| p.key());
+                if params.windows(2).any(|w| w[0].key() == w[1].key()) {
+                    return Err(DnsError::InvalidData("duplicate SvcParam key".to_string()));
+                }
+                for param in params {
+                    let value = param.to_wire()?;
+                    buffer.write_u16(param.key())?;
+                    buffer.write_u16(value.len() as u16)?;
+                    buffer.write_bytes(&value)?;
+                }
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::SOA {
+                ref domain,
+                class,
+                ref mname,
+                ref rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+                ttl,
+            } => {
+                buffer.write_name(domain)?;
+                buffer.write_u16(QueryType::SOA.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_name(mname)?;
+                buffer.write_name(rname)?;
+                buffer.write_u32(serial)?;
+                buffer.write_u32(refresh)?;
+                buffer.write_u32(retry)?;
+                buffer.write_u32(expire)?;
+                buffer.write_u32(minimum)?;
 
                 let size = buffer.pos() - (pos + 2);
                 buffer.set_u16(pos, size as u16)?;
             }
             DnsRecord::AAAA {
                 ref domain,
+                class,
                 ref addr,
                 ttl,
             } => {
-                buffer.write_qname(domain)?;
+                buffer.write_name(domain)?;
                 buffer.write_u16(QueryType::AAAA.to_num())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_num())?;
                 buffer.write_u32(ttl)?;
                 buffer.write_u16(16)?;
 
@@ -655,8 +2660,20 @@ impl DnsRecord {
                     buffer.write_u16(*octet)?;
                 }
             }
-            DnsRecord::UNKNOWN { .. } => {
-                println!("Skipping record: {:?}", self);
+            DnsRecord::UNKNOWN {
+                ref domain,
+                class,
+                qtype,
+                ref data,
+                ttl,
+            } => {
+                buffer.write_name(domain)?;
+                buffer.write_u16(qtype)?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+                buffer.write_u16(data.len() as u16)?;
+
+                buffer.write_bytes(data)?;
             }
         }
 
@@ -664,6 +2681,91 @@ impl DnsRecord {
     }
 }
 
+// # Edns
+// EDNS(0) 的 OPT 伪记录 (RFC 6891)。它不是真正的资源记录, 而是对整个报文的扩展:
+// CLASS 字段存放UDP负载大小, TTL 字段存放扩展RCODE、版本号和DO位。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EdnsOption {
+    pub code: u16,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edns {
+    pub udp_payload_size: u16,
+    pub extended_rcode: u8, // RCODE 的高8位, 由 DnsPacket 在读写时与报头中的 rescode 同步
+    pub version: u8,
+    pub dnssec_ok: bool,
+    pub options: Vec<EdnsOption>,
+}
+
+impl Edns {
+    pub fn new(udp_payload_size: u16) -> Edns {
+        Edns {
+            udp_payload_size,
+            extended_rcode: 0,
+            version: 0,
+            dnssec_ok: false,
+            options: Vec::new(),
+        }
+    }
+
+    // 从 TYPE 字段之后开始读取 (域名和类型已由调用者读出)
+    fn read(buffer: &mut BytePacketBuffer) -> Result<Edns,DnsError> {
+        let udp_payload_size = buffer.read_u16()?;
+        let ttl = buffer.read_u32()?;
+        let data_len = buffer.read_u16()? as usize;
+
+        let mut options = Vec::new();
+        let end = buffer.pos() + data_len;
+        while buffer.pos() < end {
+            let code = buffer.read_u16()?;
+            let len = buffer.read_u16()? as usize;
+            let data = buffer.read_bytes(len)?;
+            options.push(EdnsOption { code, data });
+        }
+        if buffer.pos() != end {
+            return Err(DnsError::BadRdataLength(QueryType::OPT.to_num()));
+        }
+
+        Ok(Edns {
+            udp_payload_size,
+            extended_rcode: (ttl >> 24) as u8,
+            version: ((ttl >> 16) & 0xFF) as u8,
+            dnssec_ok: (ttl & 0x8000) > 0,
+            options,
+        })
+    }
+
+    fn write(&self, buffer: &mut BytePacketBuffer) -> Result<(),DnsError> {
+        // OPT 的域名总是根域
+        buffer.write_u8(0)?;
+        buffer.write_u16(QueryType::OPT.to_num())?;
+        buffer.write_u16(self.udp_payload_size)?;
+        buffer.write_u32(
+            ((self.extended_rcode as u32) << 24)
+                | ((self.version as u32) << 16)
+                | ((self.dnssec_ok as u32) << 15),
+        )?;
+
+        let pos = buffer.pos();
+        buffer.write_u16(0)?;
+
+        for option in &self.options {
+            buffer.write_u16(option.code)?;
+            buffer.write_u16(option.data.len() as u16)?;
+            for b in &option.data {
+                buffer.write_u8(*b)?;
+            }
+        }
+
+        let size = buffer.pos() - (pos + 2);
+        buffer.set_u16(pos, size as u16)?;
+
+        Ok(())
+    }
+}
+
 // # DnsPacket
 // 最后，让我们将它们放到一个称为DnsPacket的结构中：
 #[derive(Clone, Debug)]
@@ -673,6 +2775,13 @@ pub struct DnsPacket {
     pub answers: Vec<DnsRecord>,
     pub authorities: Vec<DnsRecord>,
     pub resources: Vec<DnsRecord>,
+    pub edns: Option<Edns>, // 附加部分中的 OPT 伪记录
+}
+
+impl Default for DnsPacket {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DnsPacket {
@@ -683,15 +2792,24 @@ impl DnsPacket {
             answers: Vec::new(),
             authorities: Vec::new(),
             resources: Vec::new(),
+            edns: None,
+        }
+    }
+
+    // 客户端能接收的UDP应答大小: 没有EDNS时为512, 否则取宣告值但不超过我们自己的上限
+    pub fn max_udp_size(&self) -> usize {
+        match self.edns {
+            Some(ref edns) => (edns.udp_payload_size as usize).clamp(UDP_PACKET_SIZE, EDNS_PAYLOAD_SIZE),
+            None => UDP_PACKET_SIZE,
         }
     }
 
-    pub fn from_buffer(buffer: &mut BytePacketBuffer) -> Result<DnsPacket,Box<dyn Error>> {
+    pub fn from_buffer(buffer: &mut BytePacketBuffer) -> Result<DnsPacket,DnsError> {
         let mut result = DnsPacket::new();
         result.header.read(buffer)?;
 
         for _ in 0..result.header.questions {
-            let mut question = DnsQuestion::new("".to_string(), QueryType::UNKNOWN(0));
+            let mut question = DnsQuestion::new(DnsName::root(), QueryType::UNKNOWN(0));
             question.read(buffer)?;
             result.questions.push(question);
         }
@@ -705,19 +2823,47 @@ impl DnsPacket {
             result.authorities.push(rec);
         }
         for _ in 0..result.header.resource_entries {
+            // 先偷看一下记录类型, OPT 伪记录单独解析
+            let start = buffer.pos();
+            buffer.read_name()?;
+            if buffer.read_u16()? == QueryType::OPT.to_num() {
+                if result.edns.is_some() {
+                    return Err(DnsError::Malformed("multiple OPT records".to_string()));
+                }
+                result.edns = Some(Edns::read(buffer)?);
+                continue;
+            }
+            buffer.seek(start)?;
+
             let rec = DnsRecord::read(buffer)?;
             result.resources.push(rec);
         }
 
+        // 合并 OPT 中扩展 RCODE 的高8位
+        if let Some(ref edns) = result.edns {
+            let rcode = ((edns.extended_rcode as u16) << 4) | result.header.rescode.to_num();
+            result.header.rescode = ResultCode::from_num(rcode);
+        }
+
         Ok(result)
     }
 
 
-    pub fn write(&mut self, buffer: &mut BytePacketBuffer) -> Result<(),Box<dyn Error>> {
+    pub fn write(&mut self, buffer: &mut BytePacketBuffer) -> Result<(),DnsError> {
         self.header.questions = self.questions.len() as u16;
         self.header.answers = self.answers.len() as u16;
         self.header.authoritative_entries = self.authorities.len() as u16;
-        self.header.resource_entries = self.resources.len() as u16;
+        self.header.resource_entries = self.resources.len() as u16 + self.edns.is_some() as u16;
+
+        // 大于15的 RCODE 需要把高8位放进 OPT 记录
+        let rcode = self.header.rescode.to_num();
+        match self.edns {
+            Some(ref mut edns) => edns.extended_rcode = (rcode >> 4) as u8,
+            None if rcode > 0x0F => {
+                return Err(DnsError::InvalidData(format!("RCODE {} requires EDNS", rcode)));
+            }
+            None => {}
+        }
 
         self.header.write(buffer)?;
 
@@ -733,73 +2879,396 @@ impl DnsPacket {
         for rec in &self.resources {
             rec.write(buffer)?;
         }
+        if let Some(ref edns) = self.edns {
+            edns.write(buffer)?;
+        }
 
         Ok(())
     }
 
+    // 去掉没有显式查询的 DNSSEC 记录, 用于把设置了DO位取回的应答交给没有设置DO位的客户端 (RFC 3225 3)
+    pub fn strip_dnssec(&mut self, qtype: QueryType) {
+        let keep = |rec: &DnsRecord| rec.dnssec_type().is_none_or(|t| t == qtype);
+        self.answers.retain(keep);
+        self.authorities.retain(keep);
+        self.resources.retain(keep);
+    }
+
+    // 判断这个报文是否是对`request`的应答: ID和问题部分都必须一致
+    pub fn is_reply_to(&self, request: &DnsPacket) -> bool {
+        self.header.response
+            && self.header.id == request.header.id
+            && self.questions == request.questions
+    }
+
+    // 写入缓冲区, 放不下时从后往前丢弃记录直到能放下为止 (RFC 2181 9)。
+    // 只丢弃附加记录时不需要设置TC位, 丢弃了回答或授权记录则必须设置。
+    pub fn write_truncated(&mut self, buffer: &mut BytePacketBuffer) -> Result<(),DnsError> {
+        loop {
+            buffer.reset();
+            let e = match self.write(buffer) {
+                Ok(_) => return Ok(()),
+                Err(e @ DnsError::BufferOverrun) => e,
+                // 其他错误(比如标签过长)丢弃记录也无济于事
+                Err(e) => return Err(e),
+            };
+
+            if self.resources.pop().is_some() {
+                continue;
+            }
+            self.header.truncated_message = true;
+            if self.authorities.pop().is_some() || self.answers.pop().is_some() {
+                continue;
+            }
+            return Err(e);
+        }
+    }
 }
 
-// # Lookup
-pub fn lookup(qname: &str,qtype: QueryType) -> Result<DnsPacket,Box<dyn Error>> {
-    let server = ("1.1.1.1", 53);
+// 通过TCP读取一个带2字节长度前缀的报文
+fn read_tcp_message(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut len_buf = [0u8; 2];
+    stream.read_exact(&mut len_buf)?;
+
+    let len = u16::from_be_bytes(len_buf) as usize;
+    let mut data = vec![0; len];
+    stream.read_exact(&mut data)?;
+
+    Ok(data)
+}
+
+// 通过TCP发送一个报文, 前面加上2字节的长度前缀
+fn write_tcp_message(stream: &mut TcpStream, data: &[u8]) -> std::io::Result<()> {
+    let mut message = Vec::with_capacity(data.len() + 2);
+    message.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    message.extend_from_slice(data);
+    stream.write_all(&message)
+}
+
+// 上游的UDP应答被截断时, 改用TCP重新查询
+fn lookup_tcp(upstream: &Upstream, budget: &mut QueryBudget, request: &DnsPacket, req_buffer: &mut BytePacketBuffer) -> Result<DnsPacket,DnsError> {
+    let timeout = budget.timeout(upstream.timeout).ok_or(DnsError::Timeout)?;
+    let mut stream = TcpStream::connect_timeout(&upstream.addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let len = req_buffer.pos();
+    write_tcp_message(&mut stream, req_buffer.get_range(0, len)?)?;
+
+    let data = read_tcp_message(&mut stream)?;
+    let result = DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&data))?;
+    if !is_expected_reply(&result, request, upstream) {
+        return Err(DnsError::MismatchedReply);
+    }
+
+    Ok(result)
+}
+
+// 开启了 0x20 的上游除了ID和问题之外, 还必须原样回显域名的大小写
+fn is_expected_reply(reply: &DnsPacket, request: &DnsPacket, upstream: &Upstream) -> bool {
+    reply.is_reply_to(request)
+        && (!upstream.randomize_case
+            || reply.questions.iter().zip(&request.questions).all(|(a, b)| a.name.eq_exact(&b.name)))
+}
+
+// 只解析报头和问题部分进行校验, 被截断的应答后面的记录可能不完整
+fn reply_matches(res_buffer: &mut BytePacketBuffer, request: &DnsPacket, upstream: &Upstream) -> bool {
+    let mut reply = DnsPacket::new();
+    if reply.header.read(res_buffer).is_err() {
+        return false;
+    }
+    for _ in 0..reply.header.questions {
+        let mut question = DnsQuestion::new(DnsName::root(), QueryType::UNKNOWN(0));
+        if question.read(res_buffer).is_err() {
+            return false;
+        }
+        reply.questions.push(question);
+    }
+
+    is_expected_reply(&reply, request, upstream)
+}
+
+// 从系统随机源读取随机字节, 用于生成事务ID和源端口
+fn random_bytes(buf: &mut [u8]) -> Result<(),DnsError> {
+    File::open("/dev/urandom")?.read_exact(buf)?;
+    Ok(())
+}
+
+fn random_u16() -> Result<u16,DnsError> {
+    let mut buf = [0u8; 2];
+    random_bytes(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+// 绑定一个随机的源端口, 让伪造应答的攻击者无法预测端口 (RFC 5452)
+fn bind_random_port(upstream: &Upstream) -> Result<UdpSocket,DnsError> {
+    let bind_addr = if upstream.addr.is_ipv4() { "0.0.0.0" } else { "::" };
+    for _ in 0..10 {
+        let port = 1024 + random_u16()? % (65535 - 1024);
+        match UdpSocket::bind((bind_addr, port)) {
+            Ok(socket) => return Ok(socket),
+            Err(ref e) if e.kind() == ErrorKind::AddrInUse => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    // 多次冲突后交给操作系统分配
+    Ok(UdpSocket::bind((bind_addr, 0))?)
+}
+
+// # QueryBudget
+// 单个客户端查询在所有上游上的尝试次数和总时限
+pub struct QueryBudget {
+    deadline: Instant,
+    attempts_left: u32,
+}
+
+impl QueryBudget {
+    pub fn new(deadline: Duration, attempts: u32) -> QueryBudget {
+        QueryBudget {
+            deadline: Instant::now() + deadline,
+            attempts_left: attempts,
+        }
+    }
+
+    // 消耗一次尝试, 次数用完或已经超过总时限时返回 false
+    fn take_attempt(&mut self) -> bool {
+        if self.attempts_left == 0 || Instant::now() >= self.deadline {
+            return false;
+        }
+        self.attempts_left -= 1;
+        true
+    }
+
+    // 本次尝试的超时时间, 不能超过剩余的总时限
+    fn timeout(&self, timeout: Duration) -> Option<Duration> {
+        let remaining = self.deadline.checked_duration_since(Instant::now())?;
+        if remaining.is_zero() {
+            return None;
+        }
+        Some(timeout.min(remaining))
+    }
+}
+
+// 依次尝试各个上游, 直到拿到可用的应答或者用完尝试次数/总时限。
+// 上游返回 SERVFAIL 或 REFUSED 时也会转向下一个上游。
+// 全部失败时返回最后一个上游的错误。
+pub fn forward(context: &ServerContext, question: &DnsQuestion, dnssec_ok: bool, checking_disabled: bool) -> Result<DnsPacket,DnsError> {
+    let mut budget = QueryBudget::new(context.config.deadline, context.config.retry_budget);
+
+    let mut last_error = DnsError::Timeout;
+    for upstream in context.upstreams() {
+        let error = match lookup(question, upstream, &mut budget, dnssec_ok, checking_disabled) {
+            Ok(result) => match result.header.rescode {
+                ResultCode::SERVFAIL | ResultCode::REFUSED => DnsError::UpstreamRcode(result.header.rescode),
+                _ => return Ok(result),
+            },
+            Err(e) => e,
+        };
+        println!("Upstream {} failed: {}", upstream.addr, error);
+        last_error = error;
+        if budget.attempts_left == 0 {
+            break;
+        }
+    }
+
+    Err(last_error)
+}
 
-    let socket = UdpSocket::bind(("0.0.0.0", 43210))?;
+// # Lookup
+pub fn lookup(question: &DnsQuestion, upstream: &Upstream, budget: &mut QueryBudget, dnssec_ok: bool, checking_disabled: bool) -> Result<DnsPacket,DnsError> {
+    let socket = bind_random_port(upstream)?;
 
     let mut packet = DnsPacket::new();
 
-    packet.header.id = 6666;
+    packet.header.id = random_u16()?;
     packet.header.questions = 1;
     packet.header.recursion_desired = true;
-    packet
-        .questions
-        .push(DnsQuestion::new(qname.to_string(), qtype));
+    // 客户端要求不做 DNSSEC 验证时, 上游也不应该验证 (RFC 4035 3.2.2)
+    packet.header.checking_disabled = checking_disabled;
+    // DNS 0x20: 随机翻转域名中字母的大小写, 上游会原样回显, 伪造应答的攻击者很难猜中
+    let mut sent = question.clone();
+    if upstream.randomize_case {
+        let mut bits = vec![0u8; question.name.wire_len()];
+        random_bytes(&mut bits)?;
+        sent.name = question.name.randomize_case(&bits);
+    }
+    packet.questions.push(sent);
+    // 通过EDNS告诉上游我们可以接收更大的UDP应答, 并转发客户端的DO位,
+    // 客户端不需要签名时上游就不会返回 RRSIG 等记录
+    let mut edns = Edns::new(EDNS_PAYLOAD_SIZE as u16);
+    edns.dnssec_ok = dnssec_ok;
+    packet.edns = Some(edns);
 
     let mut req_buffer = BytePacketBuffer::new();
     packet.write(&mut req_buffer)?;
-    socket.send_to(&req_buffer.buf[0..req_buffer.pos], server)?;
 
-    let mut res_buffer = BytePacketBuffer::new();
-    socket.recv_from(&mut res_buffer.buf)?;
+    // 超时后在同一个上游上重试, 最多 upstream.retries 次, 同时受总的尝试次数和时限限制
+    let mut attempts = 0;
+    loop {
+        if !budget.take_attempt() {
+            return Err(DnsError::Timeout);
+        }
+        let timeout = budget.timeout(upstream.timeout).ok_or(DnsError::Timeout)?;
+
+        socket.send_to(&req_buffer.buf[0..req_buffer.pos], upstream.addr)?;
+        match recv_reply(&socket, upstream, timeout, &packet) {
+            Ok(Some(mut res_buffer)) => {
+                // 先只看报头: 被截断的应答里记录可能不完整, 没必要解析
+                let mut header = DnsHeader::new();
+                header.read(&mut res_buffer)?;
+                let mut result = if header.truncated_message {
+                    lookup_tcp(upstream, budget, &packet, &mut req_buffer)?
+                } else {
+                    res_buffer.seek(0)?;
+                    DnsPacket::from_buffer(&mut res_buffer)?
+                };
+                if upstream.randomize_case {
+                    restore_case(&mut result, question);
+                }
+                return Ok(result);
+            }
+            Ok(None) if attempts < upstream.retries => attempts += 1,
+            Ok(None) => return Err(DnsError::Timeout),
+            Err(e) => return Err(e),
+        }
+    }
+}
 
-    DnsPacket::from_buffer(&mut res_buffer)
+// 把应答中随机化过的域名换回客户端原来的大小写, 避免随机的大小写进入缓存
+fn restore_case(result: &mut DnsPacket, question: &DnsQuestion) {
+    result.questions = vec![question.clone()];
+    for rec in result.answers.iter_mut()
+        .chain(result.authorities.iter_mut())
+        .chain(result.resources.iter_mut())
+    {
+        if *rec.domain() == question.name {
+            rec.set_domain(question.name.clone());
+        }
+    }
 }
 
-/// Handle a single incoming packet
-pub fn handle_query(socket: Arc<UdpSocket>, src: SocketAddr, mut req_buffer: BytePacketBuffer) -> Result<(),Box<dyn Error>> {
-    // Next, `DnsPacket::from_buffer` is used to parse the raw bytes into
-    // a `DnsPacket`.
-    let mut request = DnsPacket::from_buffer(&mut req_buffer)?;
+/// Receive a single UDP datagram
+// 缓冲区只包含实际收到的字节, 被截断的报文在解析时会报错, 而不是读到后面补的0
+pub fn recv_packet(socket: &UdpSocket) -> std::io::Result<(BytePacketBuffer, SocketAddr)> {
+    let mut buf = [0u8; EDNS_PAYLOAD_SIZE];
+    let (len, src) = socket.recv_from(&mut buf)?;
+    Ok((BytePacketBuffer::from_bytes(&buf[..len]), src))
+}
+
+// 在超时之前等待上游的应答, 丢弃来源地址、事务ID或问题部分不符的报文 (它们可能是伪造的)。
+// 超时返回 None。
+fn recv_reply(socket: &UdpSocket, upstream: &Upstream, timeout: Duration, request: &DnsPacket) -> Result<Option<BytePacketBuffer>,DnsError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        socket.set_read_timeout(Some(deadline - now))?;
+
+        let (mut res_buffer, src) = match recv_packet(socket) {
+            Ok(received) => received,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if src != upstream.addr {
+            println!("Ignoring reply from unexpected address {}", src);
+            continue;
+        }
+
+        if !reply_matches(&mut res_buffer, request, upstream) {
+            println!("Ignoring mismatched reply from {}", src);
+            continue;
+        }
+
+        res_buffer.seek(0)?;
+        return Ok(Some(res_buffer));
+    }
+}
+
+// TCP连接在没有新查询时保持的最长时间 (RFC 7766 建议为秒级)
+pub const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Resolve a single request into the response packet, shared by UDP and TCP
+pub fn resolve_query(context: &ServerContext, src: SocketAddr, mut request: DnsPacket) -> Result<DnsPacket,DnsError> {
+    // 收到的是应答时直接丢弃, 不做任何回复, 否则两个转发器之间会互相反射应答
+    if request.header.response {
+        return Err(DnsError::UnexpectedResponse);
+    }
 
     // 创建并初始化响应数据包
     let mut packet = DnsPacket::new();
     packet.header.id = request.header.id;
+    packet.header.opcode = request.header.opcode;
     packet.header.recursion_desired = true;
     packet.header.recursion_available = true;
     packet.header.response = true;
 
+    // 客户端使用了EDNS时在应答中带上我们自己的OPT, 并回显DO位 (RFC 3225)
+    if let Some(ref edns) = request.edns {
+        let mut opt = Edns::new(EDNS_PAYLOAD_SIZE as u16);
+        opt.dnssec_ok = edns.dnssec_ok;
+        // 只支持版本0, 其他版本返回 BADVERS
+        if edns.version > 0 {
+            packet.header.rescode = ResultCode::BADVERS;
+            packet.edns = Some(opt);
+            return Ok(packet);
+        }
+        packet.edns = Some(opt);
+    }
+
+    // NOTIFY、UPDATE 等操作码需要权威服务器处理, 转发给递归上游没有意义
+    if request.header.opcode != Opcode::QUERY {
+        println!("IP: {}  Unsupported opcode: {:?}", src.ip(), request.header.opcode);
+        packet.header.rescode = ResultCode::NOTIMP;
+        return Ok(packet);
+    }
+
+    let dnssec_ok = request.edns.as_ref().is_some_and(|edns| edns.dnssec_ok);
+    let checking_disabled = request.header.checking_disabled;
+    packet.header.checking_disabled = checking_disabled;
+
     // In the normal case, exactly one question is present
     if let Some(question) = request.questions.pop() {
-        println!("IP: {}  Received query: {:?}", src.ip(),question);
+        println!("IP: {}  Received query: {}{}", src.ip(),question, log_hint(&question.name));
 
         // 由于所有步骤均已设置并且符合预期，因此可以将查询转发到目标服务器。 总是有可能查询将
     // 失败，在这种情况下，`SERVFAIL`响应代码被设置为向客户端指示尽可能多的内容。 如果一切都按计划进行，那么问题和响应记录将复制到我们的响应数据包中。
-        if let Ok(result) = lookup(&question.name, question.qtype.clone()) {
-            packet.questions.push(question);
-            packet.header.rescode = result.header.rescode;
-            for rec in result.answers {
-                println!("Answer: {:?}", rec);
-                packet.answers.push(rec);
-            }
-            for rec in result.authorities {
-                println!("Authority: {:?}", rec);
-                packet.authorities.push(rec);
+        // 先查缓存, 没有命中才转发给上游
+        let key = CacheKey::new(&question.name, question.qtype, question.qclass);
+        let result = match context.cache.lookup(&key, dnssec_ok) {
+            Some(result) => Ok(result),
+            // 没有经过验证的应答不能缓存, 否则会被用来回答要求验证的客户端
+            None => forward(context, &question, dnssec_ok, checking_disabled).inspect(|result| {
+                if !checking_disabled {
+                    context.cache.store(key, result, dnssec_ok);
+                }
+            }),
+        };
+        match result {
+            Ok(result) => {
+                packet.questions.push(question);
+                packet.header.rescode = result.header.rescode;
+                // 只有请求 DNSSEC 的客户端才会理解 AD 位 (RFC 3225)
+                packet.header.authed_data = dnssec_ok && result.header.authed_data;
+                for rec in result.answers {
+                    println!("Answer: {}{}", rec, log_hint(rec.domain()));
+                    packet.answers.push(rec);
+                }
+                for rec in result.authorities {
+                    println!("Authority: {}{}", rec, log_hint(rec.domain()));
+                    packet.authorities.push(rec);
+                }
+                for rec in result.resources {
+                    println!("Resource: {}{}", rec, log_hint(rec.domain()));
+                    packet.resources.push(rec);
+                }
             }
-            for rec in result.resources {
-                println!("Resource: {:?}", rec);
-                packet.resources.push(rec);
+            // 上游超时、出错或返回了错误码, 都告诉客户端服务器失败
+            Err(e) => {
+                println!("Query failed: {}", e);
+                packet.header.rescode = ResultCode::SERVFAIL;
             }
-        } else {
-            packet.header.rescode = ResultCode::SERVFAIL;
         }
     }
     // 注意来自任意发件人的输入数据有多不可靠，我们需要确保确实存在问题。 如果没有，我们将返回“ FORMERR”以表明发送者做错了什么。
@@ -807,9 +3276,57 @@ pub fn handle_query(socket: Arc<UdpSocket>, src: SocketAddr, mut req_buffer: Byt
         packet.header.rescode = ResultCode::FORMERR;
     }
 
-    //剩下的唯一事情就是对我们的响应进行编码并发送出去！
-    let mut res_buffer = BytePacketBuffer::new();
-    packet.write(&mut res_buffer)?;
+    // 客户端没有使用EDNS时无法表示上游返回的扩展 RCODE, 改为 SERVFAIL (RFC 6891 6.1.3)
+    if packet.edns.is_none() && packet.header.rescode.to_num() > 0x0F {
+        packet.header.rescode = ResultCode::SERVFAIL;
+    }
+
+    Ok(packet)
+}
+
+// 日志中在区域文件格式后面附上国际化域名的 Unicode 形式
+fn log_hint(name: &DnsName) -> String {
+    name.unicode_hint().map_or_else(String::new, |unicode| format!("  ({})", unicode))
+}
+
+// 请求无法解析时的应答: 格式错误且报头完整时回复 FORMERR,
+// 其他情况(连事务ID都读不出来, 或者不是格式问题)返回错误, 由调用者丢弃该报文
+fn format_error(req_buffer: &mut BytePacketBuffer, e: DnsError) -> Result<DnsPacket,DnsError> {
+    if !e.is_format_error() {
+        return Err(e);
+    }
+    let mut header = DnsHeader::new();
+    req_buffer.seek(0)?;
+    if header.read(req_buffer).is_err() || header.response {
+        return Err(e);
+    }
+
+    println!("Malformed query: {}", e);
+    let mut packet = DnsPacket::new();
+    packet.header.id = header.id;
+    packet.header.opcode = header.opcode;
+    packet.header.recursion_desired = header.recursion_desired;
+    packet.header.response = true;
+    packet.header.rescode = ResultCode::FORMERR;
+    Ok(packet)
+}
+
+/// Handle a single incoming packet
+pub fn handle_query(context: Arc<ServerContext>, socket: Arc<UdpSocket>, src: SocketAddr, mut req_buffer: BytePacketBuffer) -> Result<(),DnsError> {
+    // Next, `DnsPacket::from_buffer` is used to parse the raw bytes into
+    // a `DnsPacket`.
+    let (mut packet, max_size) = match DnsPacket::from_buffer(&mut req_buffer) {
+        Ok(request) => {
+            let max_size = request.max_udp_size();
+            (resolve_query(&context, src, request)?, max_size)
+        }
+        Err(e) => (format_error(&mut req_buffer, e)?, UDP_PACKET_SIZE),
+    };
+
+    //剩下的唯一事情就是对我们的响应进行编码并发送出去！超出客户端能接收的大小时设置TC位, 让客户端改用TCP
+    let mut res_buffer = BytePacketBuffer::with_size(max_size);
+    res_buffer.set_compression(context.config.compression);
+    packet.write_truncated(&mut res_buffer)?;
 
     let len = res_buffer.pos();
     let data = res_buffer.get_range(0, len)?;
@@ -818,3 +3335,35 @@ pub fn handle_query(socket: Arc<UdpSocket>, src: SocketAddr, mut req_buffer: Byt
 
     Ok(())
 }
+
+/// Handle a TCP connection, answering every length-prefixed query on it in order
+pub fn handle_tcp_connection(context: Arc<ServerContext>, mut stream: TcpStream) -> Result<(),DnsError> {
+    let src = stream.peer_addr()?;
+    stream.set_read_timeout(Some(TCP_IDLE_TIMEOUT))?;
+    stream.set_write_timeout(Some(TCP_IDLE_TIMEOUT))?;
+
+    // 同一个连接上可以连续(流水线)发送多个查询, 每个查询前有2字节的长度前缀
+    loop {
+        let data = match read_tcp_message(&mut stream) {
+            Ok(data) => data,
+            // 对端关闭连接或空闲超时, 正常结束
+            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof
+                || e.kind() == ErrorKind::WouldBlock
+                || e.kind() == ErrorKind::TimedOut => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut req_buffer = BytePacketBuffer::from_bytes(&data);
+        let mut packet = match DnsPacket::from_buffer(&mut req_buffer) {
+            Ok(request) => resolve_query(&context, src, request)?,
+            Err(e) => format_error(&mut req_buffer, e)?,
+        };
+
+        let mut res_buffer = BytePacketBuffer::growable(MAX_PACKET_SIZE);
+        res_buffer.set_compression(context.config.compression);
+        packet.write_truncated(&mut res_buffer)?;
+
+        let len = res_buffer.pos();
+        write_tcp_message(&mut stream, res_buffer.get_range(0, len)?)?;
+    }
+}
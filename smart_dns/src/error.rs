@@ -0,0 +1,91 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::core_dns::ResultCode;
+
+// # DnsError
+// 协议处理中可能出现的错误。调用者可以据此区分是客户端发来的报文有问题(FORMERR),
+// 还是上游出了问题(SERVFAIL)。
+#[derive(Debug)]
+pub enum DnsError {
+    // 读写超出了缓冲区的范围
+    BufferOverrun,
+    // 标签超过63字节
+    LabelTooLong(usize),
+    // 域名编码后超过255字节
+    NameTooLong(usize),
+    // 域名压缩指针跳转次数过多, 多半是构造的循环
+    PointerLoop,
+    // 记录实际占用的长度与 RDLENGTH 不符
+    BadRdataLength(u16),
+    // 收到的报文格式错误
+    Malformed(String),
+    // 要写出的数据不合法 (例如超过255字节的字符串)
+    InvalidData(String),
+    // 文本形式(区域文件格式)的记录无法解析
+    Syntax(String),
+    // 上游的应答与我们的查询对不上
+    MismatchedReply,
+    // 客户端发来的是应答(QR=1)而不是查询
+    UnexpectedResponse,
+    Io(io::Error),
+    // 上游没有在时限内应答
+    Timeout,
+    // 上游返回了错误码
+    UpstreamRcode(ResultCode),
+}
+
+impl DnsError {
+    // 是否是因为对方发来的报文格式不对, 这种情况应该回复 FORMERR
+    pub fn is_format_error(&self) -> bool {
+        matches!(
+            *self,
+            DnsError::BufferOverrun
+                | DnsError::LabelTooLong(_)
+                | DnsError::NameTooLong(_)
+                | DnsError::PointerLoop
+                | DnsError::BadRdataLength(_)
+                | DnsError::Malformed(_)
+        )
+    }
+}
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DnsError::BufferOverrun => write!(f, "End of buffer"),
+            DnsError::LabelTooLong(len) => write!(f, "Single label exceeds 63 characters of length: {}", len),
+            DnsError::NameTooLong(len) => write!(f, "Domain name exceeds 255 bytes of length: {}", len),
+            DnsError::PointerLoop => write!(f, "Too many compression pointer jumps"),
+            DnsError::BadRdataLength(qtype) => write!(f, "Bad rdata length for record type {}", qtype),
+            DnsError::Malformed(ref msg) => write!(f, "Malformed packet: {}", msg),
+            DnsError::InvalidData(ref msg) => write!(f, "Invalid data: {}", msg),
+            DnsError::Syntax(ref msg) => write!(f, "Syntax error: {}", msg),
+            DnsError::MismatchedReply => write!(f, "Mismatched reply from upstream"),
+            DnsError::UnexpectedResponse => write!(f, "Received a response instead of a query"),
+            DnsError::Io(ref e) => write!(f, "I/O error: {}", e),
+            DnsError::Timeout => write!(f, "Upstream timed out"),
+            DnsError::UpstreamRcode(rcode) => write!(f, "Upstream answered {:?}", rcode),
+        }
+    }
+}
+
+impl Error for DnsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            DnsError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DnsError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            // 设置了读超时的套接字超时时返回 WouldBlock 或 TimedOut
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => DnsError::Timeout,
+            _ => DnsError::Io(e),
+        }
+    }
+}
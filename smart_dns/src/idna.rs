@@ -0,0 +1,364 @@
+use std::borrow::Cow;
+
+use crate::error::DnsError;
+
+mod tables;
+
+// # IDNA
+// 国际化域名的标签转换 (RFC 5890/5891): Unicode 形式的 U-label 与 `xn--` 开头的 A-label 之间互相转换,
+// 编码使用 Punycode (RFC 3492)。
+// 输入先按 RFC 5895 映射: 转成小写、全角/半角字符换成普通宽度、做 NFC 规范化,
+// 表意句号等在拆分标签之前换成 `.`; 再按 RFC 5892 的码点属性检查, 不允许的码点直接拒绝。
+// 属性表由 scripts/gen_idna_tables.py 生成, 比表的 Unicode 版本新的码点按未分配处理。
+// 限制: 不检查双向文字规则 (RFC 5893); ZWNJ/ZWJ 只允许出现在 Virama 之后, 不支持按字形连接类型判断的情况。
+
+// A-label 的前缀
+pub const ACE_PREFIX: &str = "xn--";
+
+// 韩文音节的算法分解与组合 (Unicode 3.12)
+const S_BASE: u32 = 0xAC00;
+const L_BASE: u32 = 0x1100;
+const V_BASE: u32 = 0x1161;
+const T_BASE: u32 = 0x11A7;
+const L_COUNT: u32 = 19;
+const V_COUNT: u32 = 21;
+const T_COUNT: u32 = 28;
+const N_COUNT: u32 = V_COUNT * T_COUNT;
+const S_COUNT: u32 = L_COUNT * N_COUNT;
+
+// Virama 的组合类, ZWNJ/ZWJ 只能跟在它后面
+const VIRAMA: u8 = 9;
+
+// Punycode 参数 (RFC 3492 5)
+const BASE: u32 = 36;
+const TMIN: u32 = 1;
+const TMAX: u32 = 26;
+const SKEW: u32 = 38;
+const DAMP: u32 = 700;
+const INITIAL_BIAS: u32 = 72;
+const INITIAL_N: u32 = 128;
+
+// 把一个标签转换成 A-label。纯 ASCII 的标签原样返回。
+pub fn to_ascii(label: &str) -> Result<String, DnsError> {
+    if label.is_ascii() {
+        return Ok(label.to_string());
+    }
+
+    let label: String = label.to_lowercase().chars().map(map_width).collect();
+    let label = nfc(&label);
+    // 全角的 ASCII 字符映射后就是普通的 ASCII 标签
+    if label.is_ascii() {
+        return Ok(label);
+    }
+    let invalid = |reason: &str| DnsError::InvalidData(format!("invalid IDN label {}: {}", label, reason));
+    if label.starts_with('-') || label.ends_with('-') {
+        return Err(invalid("leading or trailing hyphen"));
+    }
+    if label.get(2..4) == Some("--") {
+        return Err(invalid("hyphens in the third and fourth positions"));
+    }
+
+    let chars: Vec<char> = label.chars().collect();
+    if in_ranges(tables::MARK, chars[0] as u32) {
+        return Err(invalid("leading combining mark"));
+    }
+    for (i, &c) in chars.iter().enumerate() {
+        if !valid_code_point(&chars, i) {
+            return Err(invalid(&format!("code point U+{:04X} is not allowed", c as u32)));
+        }
+    }
+
+    let encoded = encode(&chars).ok_or_else(|| invalid("punycode overflow"))?;
+    Ok(format!("{}{}", ACE_PREFIX, encoded))
+}
+
+// 中文输入法常见的句号 (U+3002 表意句号、U+FF0E 全角句号、U+FF61 半角表意句号) 都当作标签分隔符
+pub fn map_dots(name: &str) -> Cow<'_, str> {
+    let is_dot = |c: char| matches!(c, '\u{3002}' | '\u{FF0E}' | '\u{FF61}');
+    if name.contains(is_dot) {
+        Cow::Owned(name.replace(is_dot, "."))
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+fn map_width(c: char) -> char {
+    match tables::WIDTH.binary_search_by_key(&(c as u32), |&(from, _)| from) {
+        Ok(i) => char::from_u32(tables::WIDTH[i].1).unwrap_or(c),
+        Err(_) => c,
+    }
+}
+
+// 在按起点排序且互不重叠的区间表中查找码点所在的区间
+fn find_range<T>(table: &[T], cp: u32, range: impl Fn(&T) -> (u32, u32)) -> Option<&T> {
+    let i = table.partition_point(|entry| range(entry).1 < cp);
+    table.get(i).filter(|entry| range(entry).0 <= cp)
+}
+
+fn in_ranges(table: &[(u32, u32)], cp: u32) -> bool {
+    find_range(table, cp, |&(lo, hi)| (lo, hi)).is_some()
+}
+
+// 码点属性为 PVALID, 或者满足 CONTEXTJ/CONTEXTO 的上下文规则 (RFC 5892 附录A)
+fn valid_code_point(label: &[char], i: usize) -> bool {
+    let cp = label[i] as u32;
+    if in_ranges(tables::PVALID, cp) {
+        return true;
+    }
+    let before = i.checked_sub(1).map(|j| label[j] as u32);
+    let after = label.get(i + 1).map(|&c| c as u32);
+    let contains = |lo: u32, hi: u32| label.iter().any(|&c| (lo..=hi).contains(&(c as u32)));
+    match cp {
+        // ZERO WIDTH NON-JOINER / ZERO WIDTH JOINER
+        0x200C | 0x200D => before.is_some_and(|b| combining_class(b) == VIRAMA),
+        // MIDDLE DOT 只能出现在两个 l 之间 (加泰罗尼亚语)
+        0x00B7 => before == Some(0x6C) && after == Some(0x6C),
+        // GREEK LOWER NUMERAL SIGN (KERAIA) 后面必须是希腊字母
+        0x0375 => after.is_some_and(|a| matches!(a, 0x0370..=0x03E1 | 0x03F0..=0x03FF | 0x1F00..=0x1FFF)),
+        // HEBREW PUNCTUATION GERESH / GERSHAYIM 前面必须是希伯来字母
+        0x05F3 | 0x05F4 => before.is_some_and(|b| matches!(b, 0x0591..=0x05F2 | 0xFB1D..=0xFB4F)),
+        // KATAKANA MIDDLE DOT 要求标签中有平假名、片假名或汉字
+        0x30FB => label.iter().any(|&c| {
+            let c = c as u32;
+            c != 0x30FB && (matches!(c, 0x3041..=0x30FF | 0x31F0..=0x31FF) || is_han(c))
+        }),
+        // 阿拉伯-印度数字和扩展阿拉伯-印度数字不能混用
+        0x0660..=0x0669 => !contains(0x06F0, 0x06F9),
+        0x06F0..=0x06F9 => !contains(0x0660, 0x0669),
+        _ => false,
+    }
+}
+
+fn is_han(cp: u32) -> bool {
+    matches!(
+        cp,
+        0x3005 | 0x3007 | 0x3021..=0x3029 | 0x3038..=0x303B | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x3134F
+    )
+}
+
+fn combining_class(cp: u32) -> u8 {
+    find_range(tables::COMBINING_CLASS, cp, |&(lo, hi, _)| (lo, hi)).map_or(0, |&(_, _, class)| class)
+}
+
+// NFC 规范化 (UAX #15): 完全规范分解, 按组合类重新排序, 再做规范组合
+pub fn nfc(s: &str) -> String {
+    let mut chars = Vec::with_capacity(s.len());
+    for c in s.chars() {
+        decompose(c as u32, &mut chars);
+    }
+
+    // 相邻的非起始字符按组合类稳定排序
+    for i in 1..chars.len() {
+        let mut j = i;
+        while j > 0 {
+            let class = combining_class(chars[j]);
+            if class == 0 || combining_class(chars[j - 1]) <= class {
+                break;
+            }
+            chars.swap(j - 1, j);
+            j -= 1;
+        }
+    }
+
+    // 每个字符尝试与前面最近的起始字符组合, 中间有组合类不小于它的字符时被阻断
+    let mut out: Vec<u32> = Vec::with_capacity(chars.len());
+    let mut starter: Option<usize> = None;
+    let mut last_class = 0;
+    for cp in chars {
+        let class = combining_class(cp);
+        if let Some(pos) = starter {
+            if out.len() == pos + 1 || last_class < class {
+                if let Some(composed) = compose(out[pos], cp) {
+                    out[pos] = composed;
+                    continue;
+                }
+            }
+        }
+        if class == 0 {
+            starter = Some(out.len());
+        }
+        last_class = class;
+        out.push(cp);
+    }
+
+    out.into_iter().filter_map(char::from_u32).collect()
+}
+
+fn decompose(cp: u32, out: &mut Vec<u32>) {
+    if (S_BASE..S_BASE + S_COUNT).contains(&cp) {
+        let index = cp - S_BASE;
+        out.push(L_BASE + index / N_COUNT);
+        out.push(V_BASE + (index % N_COUNT) / T_COUNT);
+        if !index.is_multiple_of(T_COUNT) {
+            out.push(T_BASE + index % T_COUNT);
+        }
+        return;
+    }
+    match tables::DECOMPOSITION.binary_search_by_key(&cp, |&(c, _, _)| c) {
+        Ok(i) => {
+            let (_, first, second) = tables::DECOMPOSITION[i];
+            decompose(first, out);
+            if second != 0 {
+                decompose(second, out);
+            }
+        }
+        Err(_) => out.push(cp),
+    }
+}
+
+fn compose(first: u32, second: u32) -> Option<u32> {
+    if (L_BASE..L_BASE + L_COUNT).contains(&first) && (V_BASE..V_BASE + V_COUNT).contains(&second) {
+        return Some(S_BASE + ((first - L_BASE) * V_COUNT + (second - V_BASE)) * T_COUNT);
+    }
+    if (S_BASE..S_BASE + S_COUNT).contains(&first)
+        && (first - S_BASE).is_multiple_of(T_COUNT)
+        && (T_BASE + 1..T_BASE + T_COUNT).contains(&second)
+    {
+        return Some(first + (second - T_BASE));
+    }
+    tables::COMPOSITION
+        .binary_search_by_key(&(first, second), |&(a, b, _)| (a, b))
+        .ok()
+        .map(|i| tables::COMPOSITION[i].2)
+}
+
+// 把 A-label 转换成 Unicode 形式, 不是合法的 A-label 时返回 None
+pub fn to_unicode(label: &str) -> Option<String> {
+    if label.len() <= ACE_PREFIX.len() || !label[..ACE_PREFIX.len()].eq_ignore_ascii_case(ACE_PREFIX) {
+        return None;
+    }
+    let decoded: String = decode(&label[ACE_PREFIX.len()..].to_ascii_lowercase())?.into_iter().collect();
+
+    // 解码结果必须能编码回同一个 A-label, 排除纯 ASCII 的伪 A-label 等情况
+    match to_ascii(&decoded) {
+        Ok(ref ascii) if ascii.eq_ignore_ascii_case(label) && !decoded.is_ascii() => Some(decoded),
+        _ => None,
+    }
+}
+
+fn threshold(k: u32, bias: u32) -> u32 {
+    if k <= bias {
+        TMIN
+    } else if k >= bias + TMAX {
+        TMAX
+    } else {
+        k - bias
+    }
+}
+
+// 偏差调整 (RFC 3492 6.1)
+fn adapt(delta: u32, num_points: u32, first_time: bool) -> u32 {
+    let mut delta = if first_time { delta / DAMP } else { delta / 2 };
+    delta += delta / num_points;
+    let mut k = 0;
+    while delta > ((BASE - TMIN) * TMAX) / 2 {
+        delta /= BASE - TMIN;
+        k += BASE;
+    }
+    k + (BASE - TMIN + 1) * delta / (delta + SKEW)
+}
+
+fn encode_digit(d: u32) -> char {
+    match d {
+        0..=25 => (b'a' + d as u8) as char,
+        _ => (b'0' + (d - 26) as u8) as char,
+    }
+}
+
+fn decode_digit(c: u8) -> Option<u32> {
+    match c {
+        b'a'..=b'z' => Some((c - b'a') as u32),
+        b'0'..=b'9' => Some((c - b'0') as u32 + 26),
+        _ => None,
+    }
+}
+
+// Punycode 编码 (RFC 3492 6.3), 溢出时返回 None
+fn encode(input: &[char]) -> Option<String> {
+    let mut output: String = input.iter().filter(|c| c.is_ascii()).collect();
+    let basic = output.len() as u32;
+    let mut handled = basic;
+    if basic > 0 {
+        output.push('-');
+    }
+
+    let mut n = INITIAL_N;
+    let mut delta: u32 = 0;
+    let mut bias = INITIAL_BIAS;
+    while (handled as usize) < input.len() {
+        let m = input.iter().map(|&c| c as u32).filter(|&c| c >= n).min()?;
+        delta = delta.checked_add((m - n).checked_mul(handled + 1)?)?;
+        n = m;
+        for &c in input {
+            let c = c as u32;
+            if c < n {
+                delta = delta.checked_add(1)?;
+            }
+            if c == n {
+                let mut q = delta;
+                let mut k = BASE;
+                loop {
+                    let t = threshold(k, bias);
+                    if q < t {
+                        break;
+                    }
+                    output.push(encode_digit(t + (q - t) % (BASE - t)));
+                    q = (q - t) / (BASE - t);
+                    k += BASE;
+                }
+                output.push(encode_digit(q));
+                bias = adapt(delta, handled + 1, handled == basic);
+                delta = 0;
+                handled += 1;
+            }
+        }
+        delta += 1;
+        n += 1;
+    }
+
+    Some(output)
+}
+
+// Punycode 解码 (RFC 3492 6.2), 输入不合法或溢出时返回 None
+fn decode(input: &str) -> Option<Vec<char>> {
+    let (basic, extended) = match input.rfind('-') {
+        Some(i) => (&input[..i], &input[i + 1..]),
+        None => ("", input),
+    };
+    if !basic.is_ascii() {
+        return None;
+    }
+    let mut output: Vec<char> = basic.chars().collect();
+
+    let mut n = INITIAL_N;
+    let mut i: u32 = 0;
+    let mut bias = INITIAL_BIAS;
+    let mut digits = extended.bytes();
+    while digits.len() > 0 {
+        let old_i = i;
+        let mut w: u32 = 1;
+        let mut k = BASE;
+        loop {
+            let digit = decode_digit(digits.next()?)?;
+            i = i.checked_add(digit.checked_mul(w)?)?;
+            let t = threshold(k, bias);
+            if digit < t {
+                break;
+            }
+            w = w.checked_mul(BASE - t)?;
+            k += BASE;
+        }
+        let len = output.len() as u32 + 1;
+        bias = adapt(i - old_i, len, old_i == 0);
+        n = n.checked_add(i / len)?;
+        i %= len;
+        // 解码出的必须是非 ASCII 字符
+        if n < INITIAL_N {
+            return None;
+        }
+        output.insert(i as usize, char::from_u32(n)?);
+        i += 1;
+    }
+
+    Some(output)
+}
@@ -1 +1,7 @@
-pub mod core_dns;
\ No newline at end of file
+pub mod cache;
+pub mod config;
+pub mod context;
+pub mod core_dns;
+pub mod error;
+pub mod idna;
+pub mod zone;
@@ -1,20 +1,54 @@
+use std::env;
 use std::error::Error;
-use std::net::UdpSocket;
+use std::net::{TcpListener, UdpSocket};
+use std::path::Path;
 use std::thread;
 use smart_dns::*;
 use std::sync::Arc;
 
+const DEFAULT_CONFIG: &str = "smart_dns.conf";
+
 fn main() ->Result<(),Box<dyn Error>> {
+    // 配置文件路径可以通过第一个参数指定, 否则读取当前目录下的 smart_dns.conf (不存在则使用默认配置)
+    let config = match env::args().nth(1) {
+        Some(path) => config::Config::load(path)?,
+        None if Path::new(DEFAULT_CONFIG).exists() => config::Config::load(DEFAULT_CONFIG)?,
+        None => config::Config::default(),
+    };
+    println!("Upstreams: {:?}", config.upstreams);
+    let context = Arc::new(context::ServerContext::new(config));
+
     // Bind an UDP socket on port 53  (53需要root权限)
     let socket = Arc::new(UdpSocket::bind(("0.0.0.0", 53))?);
+    // 同一地址上的TCP监听, 用于被截断后重试的客户端
+    let listener = TcpListener::bind(("0.0.0.0", 53))?;
+
+    let tcp_context = context.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let context = tcp_context.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = core_dns::handle_tcp_connection(context, stream) {
+                            println!("Err: {}",e);
+                        }
+                    });
+                },
+                Err(e) => {
+                    println!("tcp accept err: {}",e);
+                }
+            }
+        }
+    });
 
     loop {
-        let mut req_buffer = core_dns::BytePacketBuffer::new();
-        match socket.recv_from(&mut req_buffer.buf) {
-            Ok((_,addr)) => {
+        match core_dns::recv_packet(&socket) {
+            Ok((req_buffer,addr)) => {
                 let socket_clone = socket.clone();
+                let context = context.clone();
                 thread::spawn(move || {
-                    match core_dns::handle_query(socket_clone,addr,req_buffer) {
+                    match core_dns::handle_query(context,socket_clone,addr,req_buffer) {
                         Ok(_) => {},
                         Err(e) => println!("Err: {}",e),
                     }
@@ -0,0 +1,865 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::core_dns::{base64_decode, base64_encode, BytePacketBuffer, DnsName, DnsQuestion, DnsRecord, QueryClass, QueryType, SvcParam, valid_caa_tag, MAX_PACKET_SIZE};
+use crate::error::DnsError;
+
+// # Zone file
+// 记录的区域文件(master file)表示形式 (RFC 1035 5.1), 每条记录一行:
+// www.example.com. 300 IN A 1.2.3.4
+// 解析时 TTL 和类别的顺序可以互换, 类别可以省略(默认 IN); 不支持 $ORIGIN、$TTL 和 @ 等指令,
+// 域名一律按绝对域名处理。括号可以把一条记录分成多行, `;` 之后是注释。
+
+// 域名写成带结尾点的绝对形式
+fn fqdn(name: &DnsName) -> String {
+    if name.is_root() {
+        ".".to_string()
+    } else {
+        format!("{}.", name)
+    }
+}
+
+// <character-string>: 总是加引号, 引号和反斜杠前加反斜杠, 不可打印的字节写成 \DDD
+fn quote(data: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for &b in data {
+        match b {
+            b'"' | b'\\' => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            0x20..=0x7E => out.push(b as char),
+            _ => out.push_str(&format!("\\{:03}", b)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+fn hex_decode(text: &str) -> Result<Vec<u8>, DnsError> {
+    let invalid = || DnsError::Syntax(format!("invalid hex: {}", text));
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return Err(invalid());
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).map_err(|_| invalid()))
+        .collect()
+}
+
+// NSEC3 中的哈希使用不带填充的 base32hex (RFC 5155 3.3, RFC 4648 7)
+const BASE32HEX_CHARS: &[u8; 32] = b"0123456789ABCDEFGHIJKLMNOPQRSTUV";
+
+fn base32hex_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &b in data {
+        buffer = (buffer << 8) | b as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32HEX_CHARS[(buffer >> bits) as usize & 0x1F] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32HEX_CHARS[(buffer << (5 - bits)) as usize & 0x1F] as char);
+    }
+    out
+}
+
+fn base32hex_decode(text: &str) -> Result<Vec<u8>, DnsError> {
+    let mut out = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let value = BASE32HEX_CHARS
+            .iter()
+            .position(|&x| x == c.to_ascii_uppercase())
+            .ok_or_else(|| DnsError::Syntax(format!("invalid base32hex: {}", text)))?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+// RRSIG 中的时间写成 YYYYMMDDHHmmSS (UTC), 也接受直接写秒数 (RFC 4034 3.2)
+fn format_time(secs: u32) -> String {
+    let days = (secs / 86400) as i64;
+    let rest = secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        rest / 3600,
+        rest / 60 % 60,
+        rest % 60
+    )
+}
+
+fn parse_time(text: &str) -> Result<u32, DnsError> {
+    let invalid = || DnsError::Syntax(format!("invalid time: {}", text));
+    if text.len() != 14 {
+        return text.parse().map_err(|_| invalid());
+    }
+    if !text.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let field = |range: std::ops::Range<usize>| text[range].parse::<i64>().unwrap();
+    let (month, day, hour, minute, second) = (field(4..6), field(6..8), field(8..10), field(10..12), field(12..14));
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 59 {
+        return Err(invalid());
+    }
+    let secs = days_from_civil(field(0..4), month, day) * 86400 + hour * 3600 + minute * 60 + second;
+    u32::try_from(secs).map_err(|_| invalid())
+}
+
+// 公历日期与1970-01-01以来天数的互相转换
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+fn type_list(types: &[u16]) -> String {
+    types.iter().map(|&t| format!(" {}", QueryType::from_num(t))).collect()
+}
+
+impl fmt::Display for DnsQuestion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", fqdn(&self.name), self.qclass, self.qtype)
+    }
+}
+
+impl fmt::Display for DnsRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} ", fqdn(self.domain()), self.ttl(), self.class())?;
+        match *self {
+            // 未解析的 rdata 使用通用格式: TYPEnnn \# 长度 十六进制数据 (RFC 3597 5)。
+            // 类型号可能是已知类型 (例如出现在应答部分的 OPT), 所以不能写助记符
+            DnsRecord::UNKNOWN { qtype, .. } => write!(f, "TYPE{}", qtype)?,
+            _ => write!(f, "{}", self.qtype())?,
+        }
+        match *self {
+            DnsRecord::UNKNOWN { ref data, .. } => {
+                write!(f, " \\# {}", data.len())?;
+                if !data.is_empty() {
+                    write!(f, " {}", hex_encode(data))?;
+                }
+                Ok(())
+            }
+            DnsRecord::A { ref addr, .. } => write!(f, " {}", addr),
+            DnsRecord::AAAA { ref addr, .. } => write!(f, " {}", addr),
+            DnsRecord::NS { ref host, .. }
+            | DnsRecord::CNAME { ref host, .. }
+            | DnsRecord::PTR { ref host, .. } => write!(f, " {}", fqdn(host)),
+            DnsRecord::SOA {
+                ref mname,
+                ref rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+                ..
+            } => write!(
+                f,
+                " {} {} {} {} {} {} {}",
+                fqdn(mname),
+                fqdn(rname),
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum
+            ),
+            DnsRecord::MX { priority, ref host, .. } => write!(f, " {} {}", priority, fqdn(host)),
+            DnsRecord::TXT { ref data, .. } => {
+                for s in data {
+                    write!(f, " {}", quote(s))?;
+                }
+                Ok(())
+            }
+            DnsRecord::SRV {
+                priority,
+                weight,
+                port,
+                ref host,
+                ..
+            } => write!(f, " {} {} {} {}", priority, weight, port, fqdn(host)),
+            DnsRecord::NAPTR {
+                order,
+                preference,
+                ref flags,
+                ref services,
+                ref regexp,
+                ref replacement,
+                ..
+            } => write!(
+                f,
+                " {} {} {} {} {} {}",
+                order,
+                preference,
+                quote(flags),
+                quote(services),
+                quote(regexp),
+                fqdn(replacement)
+            ),
+            DnsRecord::DS {
+                key_tag,
+                algorithm,
+                digest_type,
+                ref digest,
+                ..
+            } => write!(f, " {} {} {} {}", key_tag, algorithm, digest_type, hex_encode(digest)),
+            DnsRecord::SSHFP {
+                algorithm,
+                fp_type,
+                ref fingerprint,
+                ..
+            } => write!(f, " {} {} {}", algorithm, fp_type, hex_encode(fingerprint)),
+            DnsRecord::RRSIG {
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                ref signer_name,
+                ref signature,
+                ..
+            } => write!(
+                f,
+                " {} {} {} {} {} {} {} {} {}",
+                QueryType::from_num(type_covered),
+                algorithm,
+                labels,
+                original_ttl,
+                format_time(expiration),
+                format_time(inception),
+                key_tag,
+                fqdn(signer_name),
+                base64_encode(signature)
+            ),
+            DnsRecord::NSEC {
+                ref next_domain,
+                ref types,
+                ..
+            } => write!(f, " {}{}", fqdn(next_domain), type_list(types)),
+            DnsRecord::DNSKEY {
+                flags,
+                protocol,
+                algorithm,
+                ref public_key,
+                ..
+            } => write!(f, " {} {} {} {}", flags, protocol, algorithm, base64_encode(public_key)),
+            DnsRecord::NSEC3 {
+                hash_algorithm,
+                flags,
+                iterations,
+                ref salt,
+                ref next_hashed,
+                ref types,
+                ..
+            } => write!(
+                f,
+                " {} {} {} {} {}{}",
+                hash_algorithm,
+                flags,
+                iterations,
+                salt_text(salt),
+                base32hex_encode(next_hashed),
+                type_list(types)
+            ),
+            DnsRecord::NSEC3PARAM {
+                hash_algorithm,
+                flags,
+                iterations,
+                ref salt,
+                ..
+            } => write!(f, " {} {} {} {}", hash_algorithm, flags, iterations, salt_text(salt)),
+            DnsRecord::TLSA {
+                usage,
+                selector,
+                matching_type,
+                ref data,
+                ..
+            } => write!(f, " {} {} {} {}", usage, selector, matching_type, hex_encode(data)),
+            DnsRecord::SVCB {
+                priority,
+                ref target,
+                ref params,
+                ..
+            }
+            | DnsRecord::HTTPS {
+                priority,
+                ref target,
+                ref params,
+                ..
+            } => {
+                write!(f, " {} {}", priority, fqdn(target))?;
+                for param in params {
+                    write!(f, " {}", param)?;
+                }
+                Ok(())
+            }
+            DnsRecord::CAA {
+                flags,
+                ref tag,
+                ref value,
+                ..
+            } => write!(f, " {} {} {}", flags, String::from_utf8_lossy(tag), quote(value)),
+        }
+    }
+}
+
+// 空的盐写成 "-" (RFC 5155 3.3)
+fn salt_text(salt: &[u8]) -> String {
+    if salt.is_empty() {
+        "-".to_string()
+    } else {
+        hex_encode(salt)
+    }
+}
+
+// 把一行文本切分成记录的各个字段。引号内的空白不分隔字段, 反斜杠转义的字符保持原样,
+// 留给具体字段解析时再处理。
+fn tokenize(line: &str) -> Result<Vec<String>, DnsError> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                token.push(c);
+                let next = chars
+                    .next()
+                    .ok_or_else(|| DnsError::Syntax("trailing backslash".to_string()))?;
+                token.push(next);
+            }
+            '"' => {
+                token.push(c);
+                quoted = !quoted;
+            }
+            _ if quoted => token.push(c),
+            // 注释到行尾为止, 括号内的记录可以在下一行继续
+            ';' => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            '(' | ')' => {}
+            _ if c.is_whitespace() => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            _ => token.push(c),
+        }
+    }
+    if quoted {
+        return Err(DnsError::Syntax("unterminated quoted string".to_string()));
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+// 解析 \DDD 和 \X 转义
+fn unescape(text: &str) -> Result<Vec<u8>, DnsError> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let rest = &bytes[i + 1..];
+        if rest.len() >= 3 && rest[..3].iter().all(u8::is_ascii_digit) {
+            let value = rest[..3].iter().fold(0u16, |n, d| n * 10 + (d - b'0') as u16);
+            if value > 255 {
+                return Err(DnsError::Syntax(format!("invalid escape in {}", text)));
+            }
+            out.push(value as u8);
+            i += 4;
+        } else if let Some(&c) = rest.first() {
+            out.push(c);
+            i += 2;
+        } else {
+            return Err(DnsError::Syntax(format!("trailing backslash in {}", text)));
+        }
+    }
+    Ok(out)
+}
+
+// 去掉两端的引号 (如果有)
+fn unquote(text: &str) -> &str {
+    if text.len() >= 2 && text.starts_with('"') && text.ends_with('"') {
+        &text[1..text.len() - 1]
+    } else {
+        text
+    }
+}
+
+// 按未转义的逗号分隔 SvcParam 的值列表 (RFC 9460 A.1)
+fn split_value_list(text: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                item.push(c);
+                if let Some(next) = chars.next() {
+                    item.push(next);
+                }
+            }
+            ',' => items.push(std::mem::take(&mut item)),
+            _ => item.push(c),
+        }
+    }
+    items.push(item);
+    items
+}
+
+fn svc_key(name: &str) -> Result<u16, DnsError> {
+    let key = match name {
+        "mandatory" => 0,
+        "alpn" => 1,
+        "no-default-alpn" => 2,
+        "port" => 3,
+        "ipv4hint" => 4,
+        "ech" => 5,
+        "ipv6hint" => 6,
+        _ => match name.strip_prefix("key").and_then(|n| n.parse().ok()) {
+            Some(key) => key,
+            None => return Err(DnsError::Syntax(format!("unknown SvcParam key: {}", name))),
+        },
+    };
+    Ok(key)
+}
+
+// 解析 SvcParam 的表示形式, 与 SvcParam 的 Display 对应
+impl FromStr for SvcParam {
+    type Err = DnsError;
+
+    fn from_str(s: &str) -> Result<SvcParam, DnsError> {
+        let (name, value) = match s.find('=') {
+            Some(i) => (&s[..i], Some(unquote(&s[i + 1..]))),
+            None => (s, None),
+        };
+        let key = svc_key(name)?;
+        let invalid = || DnsError::Syntax(format!("invalid SvcParam: {}", s));
+        let list = |value: Option<&str>| -> Result<Vec<String>, DnsError> {
+            let value = value.ok_or_else(invalid)?;
+            Ok(split_value_list(value))
+        };
+
+        let param = match key {
+            0 => SvcParam::Mandatory(list(value)?.iter().map(|k| svc_key(k)).collect::<Result<_, _>>()?),
+            1 => SvcParam::Alpn(
+                list(value)?
+                    .iter()
+                    .map(|id| match unescape(id)? {
+                        id if id.is_empty() => Err(invalid()),
+                        id => Ok(id),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            2 if value.is_none() => SvcParam::NoDefaultAlpn,
+            3 => SvcParam::Port(value.and_then(|v| v.parse().ok()).ok_or_else(invalid)?),
+            4 => SvcParam::Ipv4Hint(
+                list(value)?
+                    .iter()
+                    .map(|a| a.parse().map_err(|_| invalid()))
+                    .collect::<Result<_, _>>()?,
+            ),
+            5 => SvcParam::Ech(value.and_then(base64_decode).ok_or_else(invalid)?),
+            6 => SvcParam::Ipv6Hint(
+                list(value)?
+                    .iter()
+                    .map(|a| a.parse().map_err(|_| invalid()))
+                    .collect::<Result<_, _>>()?,
+            ),
+            7..=65535 => SvcParam::Unknown(key, unescape(value.unwrap_or(""))?),
+            _ => return Err(invalid()),
+        };
+        Ok(param)
+    }
+}
+
+// 依次取出记录的各个字段
+struct Fields {
+    tokens: std::vec::IntoIter<String>,
+}
+
+impl Fields {
+    fn next(&mut self) -> Result<String, DnsError> {
+        self.tokens
+            .next()
+            .ok_or_else(|| DnsError::Syntax("missing field".to_string()))
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T, DnsError> {
+        let token = self.next()?;
+        token
+            .parse()
+            .map_err(|_| DnsError::Syntax(format!("invalid number: {}", token)))
+    }
+
+    fn name(&mut self) -> Result<DnsName, DnsError> {
+        self.next()?.parse()
+    }
+
+    fn character_string(&mut self) -> Result<Vec<u8>, DnsError> {
+        let data = unescape(unquote(&self.next()?))?;
+        if data.len() > 255 {
+            return Err(DnsError::Syntax("character string exceeds 255 bytes".to_string()));
+        }
+        Ok(data)
+    }
+
+    // 剩下的所有字段, 用于可以分成多段书写的 base64/十六进制数据和类型列表
+    fn rest(&mut self) -> Vec<String> {
+        self.tokens.by_ref().collect()
+    }
+
+    fn hex(&mut self) -> Result<Vec<u8>, DnsError> {
+        hex_decode(&self.rest().concat())
+    }
+
+    fn base64(&mut self) -> Result<Vec<u8>, DnsError> {
+        let text = self.rest().concat();
+        base64_decode(&text).ok_or_else(|| DnsError::Syntax(format!("invalid base64: {}", text)))
+    }
+
+    fn salt(&mut self) -> Result<Vec<u8>, DnsError> {
+        match self.next()?.as_str() {
+            "-" => Ok(Vec::new()),
+            salt => hex_decode(salt),
+        }
+    }
+
+    fn types(&mut self) -> Result<Vec<u16>, DnsError> {
+        self.rest()
+            .iter()
+            .map(|t| t.parse::<QueryType>().map(|t| t.to_num()))
+            .collect()
+    }
+
+    fn finish(&mut self) -> Result<(), DnsError> {
+        match self.tokens.next() {
+            Some(token) => Err(DnsError::Syntax(format!("unexpected field: {}", token))),
+            None => Ok(()),
+        }
+    }
+}
+
+// 已知类型的通用格式: 把原始的 rdata 写成线路格式, 再按该类型解析
+fn from_generic(record: &DnsRecord) -> Result<DnsRecord, DnsError> {
+    let mut buffer = BytePacketBuffer::growable(MAX_PACKET_SIZE);
+    record.write(&mut buffer)?;
+    DnsRecord::read(&mut BytePacketBuffer::from_bytes(&buffer.buf[..buffer.pos]))
+}
+
+// 解析一条区域文件格式的记录
+impl FromStr for DnsRecord {
+    type Err = DnsError;
+
+    fn from_str(s: &str) -> Result<DnsRecord, DnsError> {
+        let mut fields = Fields {
+            tokens: tokenize(s)?.into_iter(),
+        };
+        let domain = fields.name()?;
+
+        let mut ttl = None;
+        let mut class = None;
+        let qtype = loop {
+            let token = fields.next()?;
+            if ttl.is_none() && token.bytes().all(|b| b.is_ascii_digit()) {
+                ttl = Some(token.parse().map_err(|_| DnsError::Syntax(format!("invalid TTL: {}", token)))?);
+                continue;
+            }
+            if class.is_none() {
+                if let Ok(c) = token.parse::<QueryClass>() {
+                    class = Some(c);
+                    continue;
+                }
+            }
+            break token.parse::<QueryType>()?;
+        };
+        let ttl = ttl.ok_or_else(|| DnsError::Syntax("missing TTL".to_string()))?;
+        let class = class.unwrap_or(QueryClass::IN);
+
+        // 任何类型都可以使用通用格式: \# 长度 十六进制数据 (RFC 3597 5)
+        if fields.tokens.as_slice().first().is_some_and(|t| t == "\\#") {
+            fields.next()?;
+            let len: usize = fields.number()?;
+            let data = fields.hex()?;
+            if data.len() != len {
+                return Err(DnsError::Syntax(format!("rdata length {} does not match {}", data.len(), len)));
+            }
+            let record = DnsRecord::UNKNOWN {
+                domain,
+                class,
+                qtype: qtype.to_num(),
+                data,
+                ttl,
+            };
+            return match qtype {
+                QueryType::UNKNOWN(_) => Ok(record),
+                _ => from_generic(&record)
+                    .map_err(|e| DnsError::Syntax(format!("invalid {} rdata: {}", qtype, e))),
+            };
+        }
+
+        let record = match qtype {
+            QueryType::A => DnsRecord::A {
+                domain,
+                class,
+                addr: fields.number()?,
+                ttl,
+            },
+            QueryType::AAAA => DnsRecord::AAAA {
+                domain,
+                class,
+                addr: fields.number()?,
+                ttl,
+            },
+            QueryType::NS => DnsRecord::NS {
+                domain,
+                class,
+                host: fields.name()?,
+                ttl,
+            },
+            QueryType::CNAME => DnsRecord::CNAME {
+                domain,
+                class,
+                host: fields.name()?,
+                ttl,
+            },
+            QueryType::PTR => DnsRecord::PTR {
+                domain,
+                class,
+                host: fields.name()?,
+                ttl,
+            },
+            QueryType::SOA => DnsRecord::SOA {
+                domain,
+                class,
+                mname: fields.name()?,
+                rname: fields.name()?,
+                serial: fields.number()?,
+                refresh: fields.number()?,
+                retry: fields.number()?,
+                expire: fields.number()?,
+                minimum: fields.number()?,
+                ttl,
+            },
+            QueryType::MX => DnsRecord::MX {
+                domain,
+                class,
+                priority: fields.number()?,
+                host: fields.name()?,
+                ttl,
+            },
+            QueryType::TXT => {
+                let mut data = Vec::new();
+                while fields.tokens.len() > 0 {
+                    data.push(fields.character_string()?);
+                }
+                DnsRecord::TXT {
+                    domain,
+                    class,
+                    data,
+                    ttl,
+                }
+            }
+            QueryType::SRV => DnsRecord::SRV {
+                domain,
+                class,
+                priority: fields.number()?,
+                weight: fields.number()?,
+                port: fields.number()?,
+                host: fields.name()?,
+                ttl,
+            },
+            QueryType::NAPTR => DnsRecord::NAPTR {
+                domain,
+                class,
+                order: fields.number()?,
+                preference: fields.number()?,
+                flags: fields.character_string()?,
+                services: fields.character_string()?,
+                regexp: fields.character_string()?,
+                replacement: fields.name()?,
+                ttl,
+            },
+            QueryType::DS => DnsRecord::DS {
+                domain,
+                class,
+                key_tag: fields.number()?,
+                algorithm: fields.number()?,
+                digest_type: fields.number()?,
+                digest: fields.hex()?,
+                ttl,
+            },
+            QueryType::SSHFP => DnsRecord::SSHFP {
+                domain,
+                class,
+                algorithm: fields.number()?,
+                fp_type: fields.number()?,
+                fingerprint: fields.hex()?,
+                ttl,
+            },
+            QueryType::RRSIG => DnsRecord::RRSIG {
+                domain,
+                class,
+                type_covered: fields.next()?.parse::<QueryType>()?.to_num(),
+                algorithm: fields.number()?,
+                labels: fields.number()?,
+                original_ttl: fields.number()?,
+                expiration: parse_time(&fields.next()?)?,
+                inception: parse_time(&fields.next()?)?,
+                key_tag: fields.number()?,
+                signer_name: fields.name()?,
+                signature: fields.base64()?,
+                ttl,
+            },
+            QueryType::NSEC => DnsRecord::NSEC {
+                domain,
+                class,
+                next_domain: fields.name()?,
+                types: fields.types()?,
+                ttl,
+            },
+            QueryType::DNSKEY => DnsRecord::DNSKEY {
+                domain,
+                class,
+                flags: fields.number()?,
+                protocol: fields.number()?,
+                algorithm: fields.number()?,
+                public_key: fields.base64()?,
+                ttl,
+            },
+            QueryType::NSEC3 => DnsRecord::NSEC3 {
+                domain,
+                class,
+                hash_algorithm: fields.number()?,
+                flags: fields.number()?,
+                iterations: fields.number()?,
+                salt: fields.salt()?,
+                next_hashed: base32hex_decode(&fields.next()?)?,
+                types: fields.types()?,
+                ttl,
+            },
+            QueryType::NSEC3PARAM => DnsRecord::NSEC3PARAM {
+                domain,
+                class,
+                hash_algorithm: fields.number()?,
+                flags: fields.number()?,
+                iterations: fields.number()?,
+                salt: fields.salt()?,
+                ttl,
+            },
+            QueryType::TLSA => DnsRecord::TLSA {
+                domain,
+                class,
+                usage: fields.number()?,
+                selector: fields.number()?,
+                matching_type: fields.number()?,
+                data: fields.hex()?,
+                ttl,
+            },
+            QueryType::SVCB | QueryType::HTTPS => {
+                let priority = fields.number()?;
+                let target = fields.name()?;
+                // 表示形式中参数可以任意排列, 按线路格式的要求排好序, 但不能重复 (RFC 9460 2.1)
+                let mut params: Vec<SvcParam> = fields
+                    .rest()
+                    .iter()
+                    .map(|p| p.parse())
+                    .collect::<Result<_, _>>()?;
+                params.sort_by_key(|p| p.key());
+                if params.windows(2).any(|w| w[0].key() == w[1].key()) {
+                    return Err(DnsError::Syntax(format!("duplicate SvcParam key in {}", s)));
+                }
+                if qtype == QueryType::SVCB {
+                    DnsRecord::SVCB {
+                        domain,
+                        class,
+                        priority,
+                        target,
+                        params,
+                        ttl,
+                    }
+                } else {
+                    DnsRecord::HTTPS {
+                        domain,
+                        class,
+                        priority,
+                        target,
+                        params,
+                        ttl,
+                    }
+                }
+            }
+            QueryType::CAA => DnsRecord::CAA {
+                domain,
+                class,
+                flags: fields.number()?,
+                tag: match fields.next()?.into_bytes() {
+                    tag if valid_caa_tag(&tag) => tag,
+                    tag => return Err(DnsError::Syntax(format!("invalid CAA tag: {}", String::from_utf8_lossy(&tag)))),
+                },
+                value: unescape(unquote(&fields.next()?))?,
+                ttl,
+            },
+            QueryType::UNKNOWN(_) => {
+                return Err(DnsError::Syntax("unknown types must use the \\# generic format".to_string()))
+            }
+            QueryType::OPT => return Err(DnsError::Syntax("OPT is not a zone file record".to_string())),
+        };
+        fields.finish()?;
+
+        Ok(record)
+    }
+}
@@ -0,0 +1,161 @@
+use std::thread;
+use std::time::Duration;
+
+use smart_dns::cache::{Cache, CacheKey};
+use smart_dns::core_dns::*;
+
+fn a_record(name: &str, ttl: u32) -> DnsRecord {
+    DnsRecord::A {
+        domain: name.parse().unwrap(),
+        class: QueryClass::IN,
+        addr: "192.0.2.1".parse().unwrap(),
+        ttl,
+    }
+}
+
+fn key(name: &str, qtype: QueryType) -> CacheKey {
+    CacheKey::new(&name.parse().unwrap(), qtype, QueryClass::IN)
+}
+
+fn store_a(cache: &Cache, name: &str, ttl: u32) {
+    let mut packet = DnsPacket::new();
+    packet.answers.push(a_record(name, ttl));
+    cache.store(key(name, QueryType::A), &packet, false);
+}
+
+fn cached(cache: &Cache, name: &str) -> bool {
+    cache.lookup(&key(name, QueryType::A), false).is_some()
+}
+
+#[test]
+fn least_recently_used_entry_is_evicted() {
+    let cache = Cache::new(3);
+    store_a(&cache, "a.example", 300);
+    store_a(&cache, "b.example", 300);
+    store_a(&cache, "c.example", 300);
+
+    // 查询 a 之后 b 成为最久没有使用的条目
+    assert!(cached(&cache, "a.example"));
+    store_a(&cache, "d.example", 300);
+    assert_eq!(cache.len(), 3);
+    assert!(!cached(&cache, "b.example"));
+    assert!(cached(&cache, "a.example"));
+
+    // 重新写入已有的条目不会淘汰其他条目
+    store_a(&cache, "c.example", 300);
+    assert_eq!(cache.len(), 3);
+    store_a(&cache, "e.example", 300);
+    assert!(!cached(&cache, "d.example"));
+    assert!(cached(&cache, "c.example"));
+    assert!(cached(&cache, "e.example"));
+}
+
+#[test]
+fn ttls_decay_and_entries_expire() {
+    let cache = Cache::new(10);
+    store_a(&cache, "long.example", 300);
+    store_a(&cache, "short.example", 1);
+    store_a(&cache, "zero.example", 0);
+    assert!(!cached(&cache, "zero.example"));
+    assert_eq!(cache.len(), 2);
+
+    thread::sleep(Duration::from_millis(1100));
+    let packet = cache.lookup(&key("LONG.example", QueryType::A), false).unwrap();
+    assert_eq!(packet.answers[0].ttl(), 299);
+    assert!(!cached(&cache, "short.example"));
+    assert_eq!(cache.len(), 1);
+}
+
+#[test]
+fn zero_size_disables_the_cache() {
+    let cache = Cache::new(0);
+    store_a(&cache, "a.example", 300);
+    assert!(cache.is_empty());
+    assert!(!cached(&cache, "a.example"));
+}
+
+#[test]
+fn signed_entries_follow_the_do_bit() {
+    let cache = Cache::new(10);
+    let mut signed = DnsPacket::new();
+    signed.answers.push(a_record("example.com", 300));
+    signed.answers.push(DnsRecord::RRSIG {
+        domain: "example.com".parse().unwrap(),
+        class: QueryClass::IN,
+        type_covered: 1,
+        algorithm: 13,
+        labels: 2,
+        original_ttl: 300,
+        expiration: 1_700_000_000,
+        inception: 1_690_000_000,
+        key_tag: 2371,
+        signer_name: "example.com".parse().unwrap(),
+        signature: vec![0x7F; 64],
+        ttl: 300,
+    });
+    cache.store(key("example.com", QueryType::A), &signed, true);
+
+    // 带签名的条目对两种客户端都可用, 没有设置DO位的客户端拿不到 RRSIG
+    assert_eq!(cache.lookup(&key("example.com", QueryType::A), true).unwrap().answers.len(), 2);
+    assert_eq!(cache.lookup(&key("example.com", QueryType::A), false).unwrap().answers.len(), 1);
+
+    // 没有签名的条目不能回答设置了DO位的客户端
+    let mut unsigned = DnsPacket::new();
+    unsigned.answers.push(a_record("example.org", 300));
+    cache.store(key("example.org", QueryType::A), &unsigned, false);
+    assert!(cache.lookup(&key("example.org", QueryType::A), false).is_some());
+    assert!(cache.lookup(&key("example.org", QueryType::A), true).is_none());
+}
+
+fn soa(ttl: u32, minimum: u32) -> DnsRecord {
+    DnsRecord::SOA {
+        domain: "example.com".parse().unwrap(),
+        class: QueryClass::IN,
+        mname: "ns1.example.com".parse().unwrap(),
+        rname: "hostmaster.example.com".parse().unwrap(),
+        serial: 2024010101,
+        refresh: 7200,
+        retry: 3600,
+        expire: 1209600,
+        minimum,
+        ttl,
+    }
+}
+
+#[test]
+fn negative_answers_are_cached_with_the_soa_minimum() {
+    let cache = Cache::new(10);
+
+    // NXDOMAIN: 有效期取 SOA 的 TTL 与 MINIMUM 中较小的一个, 并写回 SOA 的 TTL
+    let mut nxdomain = DnsPacket::new();
+    nxdomain.header.rescode = ResultCode::NXDOMAIN;
+    nxdomain.authorities.push(soa(3600, 60));
+    cache.store(key("missing.example.com", QueryType::A), &nxdomain, false);
+    let cached = cache.lookup(&key("missing.example.com", QueryType::A), false).unwrap();
+    assert_eq!(cached.header.rescode, ResultCode::NXDOMAIN);
+    assert!(cached.answers.is_empty());
+    assert_eq!(cached.authorities, vec![soa(60, 60)]);
+
+    // NODATA: 域名存在但没有该类型的记录
+    let mut nodata = DnsPacket::new();
+    nodata.authorities.push(soa(30, 300));
+    cache.store(key("example.com", QueryType::AAAA), &nodata, false);
+    let cached = cache.lookup(&key("example.com", QueryType::AAAA), false).unwrap();
+    assert_eq!(cached.header.rescode, ResultCode::NOERROR);
+    assert_eq!(cached.authorities, vec![soa(30, 300)]);
+
+    // 没有 SOA 时不知道能缓存多久, 不缓存
+    let mut bare = DnsPacket::new();
+    bare.header.rescode = ResultCode::NXDOMAIN;
+    cache.store(key("bare.example.com", QueryType::A), &bare, false);
+    cache.store(key("bare.example.com", QueryType::AAAA), &DnsPacket::new(), false);
+    assert_eq!(cache.len(), 2);
+    assert!(cache.lookup(&key("bare.example.com", QueryType::A), false).is_none());
+
+    // SERVFAIL 不缓存
+    let mut servfail = DnsPacket::new();
+    servfail.header.rescode = ResultCode::SERVFAIL;
+    servfail.authorities.push(soa(3600, 60));
+    cache.store(key("broken.example.com", QueryType::A), &servfail, false);
+    assert_eq!(cache.len(), 2);
+}
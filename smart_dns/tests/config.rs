@@ -0,0 +1,73 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use smart_dns::cache::DEFAULT_CACHE_SIZE;
+use smart_dns::config::*;
+
+#[test]
+fn upstream_addresses_and_options() {
+    let upstream = Upstream::parse("1.1.1.1").unwrap();
+    assert_eq!(upstream, Upstream::new("1.1.1.1:53".parse().unwrap()));
+
+    let upstream = Upstream::parse("8.8.8.8:5353 timeout=500 retries=3 randomize_case=true").unwrap();
+    assert_eq!(upstream.addr, "8.8.8.8:5353".parse::<SocketAddr>().unwrap());
+    assert_eq!(upstream.timeout, Duration::from_millis(500));
+    assert_eq!(upstream.retries, 3);
+    assert!(upstream.randomize_case);
+
+    // IPv6 带端口时需要方括号, 不带端口时直接写地址
+    let upstream = Upstream::parse("[2606:4700:4700::1111]:853").unwrap();
+    assert_eq!(upstream.addr, "[2606:4700:4700::1111]:853".parse::<SocketAddr>().unwrap());
+    let upstream = Upstream::parse("2001:4860:4860::8888").unwrap();
+    assert_eq!(upstream.addr, "[2001:4860:4860::8888]:53".parse::<SocketAddr>().unwrap());
+
+    for bad in ["", "dns.example.com", "1.1.1.1 timeout", "1.1.1.1 timeout=soon", "1.1.1.1 port=53"] {
+        assert!(Upstream::parse(bad).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn config_file() {
+    let config = Config::parse(
+        "# 注释\n\
+         \n\
+         upstream = 1.1.1.1\n\
+         upstream = [2606:4700:4700::1111]:53 timeout=1000\n\
+         \x20 retry_budget = 6\n\
+         deadline = 3000\n\
+         cache_size = 0\n\
+         compression = false\n",
+    )
+    .unwrap();
+    assert_eq!(config.upstreams.len(), 2);
+    assert_eq!(config.upstreams[1].timeout, Duration::from_millis(1000));
+    assert_eq!(config.retry_budget, 6);
+    assert_eq!(config.deadline, Duration::from_millis(3000));
+    assert_eq!(config.cache_size, 0);
+    assert!(!config.compression);
+
+    // 没有写的项保持默认值
+    let config = Config::parse("").unwrap();
+    assert_eq!(config.upstreams, Config::default().upstreams);
+    assert_eq!(config.retry_budget, DEFAULT_RETRY_BUDGET);
+    assert_eq!(config.deadline, DEFAULT_DEADLINE);
+    assert_eq!(config.cache_size, DEFAULT_CACHE_SIZE);
+    assert!(config.compression);
+
+    // 示例配置文件必须能被加载
+    assert!(Config::load(concat!(env!("CARGO_MANIFEST_DIR"), "/smart_dns.conf")).is_ok());
+}
+
+#[test]
+fn config_errors_name_the_line() {
+    for (content, message) in [
+        ("upstream = 1.1.1.1\nport = 53", "line 2: unknown key `port`"),
+        ("cache_size", "line 1: expected `key = value`"),
+        ("# x\nupstream = 1.1.1.1 retries=many", "line 2:"),
+        ("deadline = -1", "line 1:"),
+        ("compression = off", "line 1:"),
+    ] {
+        let err = Config::parse(content).unwrap_err().to_string();
+        assert!(err.starts_with(message), "{}", err);
+    }
+}
@@ -0,0 +1,87 @@
+use smart_dns::core_dns::*;
+use smart_dns::idna;
+
+#[test]
+fn punycode_vectors() {
+    assert_eq!(idna::to_ascii("中国").unwrap(), "xn--fiqs8s");
+    assert_eq!(idna::to_ascii("测试").unwrap(), "xn--0zwm56d");
+    assert_eq!(idna::to_ascii("Bücher").unwrap(), "xn--bcher-kva");
+    assert_eq!(idna::to_ascii("example").unwrap(), "example");
+
+    assert_eq!(idna::to_unicode("xn--fiqs8s").unwrap(), "中国");
+    assert_eq!(idna::to_unicode("XN--BCHER-KVA").unwrap(), "bücher");
+    assert_eq!(idna::to_unicode("example"), None);
+    // 解码后是纯 ASCII, 或者根本不是合法的 punycode
+    assert_eq!(idna::to_unicode("xn--abc-"), None);
+    assert_eq!(idna::to_unicode("xn--@@@"), None);
+
+    assert!(idna::to_ascii("-中国").is_err());
+    assert!(idna::to_ascii("中 国").is_err());
+    assert!(idna::to_ascii("中_国").is_err());
+}
+
+#[test]
+fn labels_are_normalized_and_validated() {
+    // 分解形式先规范化成 NFC
+    assert_eq!(idna::nfc("bu\u{308}cher"), "bücher");
+    assert_eq!(idna::nfc("\u{1100}\u{1161}\u{11A8}"), "각");
+    assert_eq!(idna::nfc("a\u{301}\u{316}"), "\u{E1}\u{316}");
+    assert_eq!(idna::to_ascii("Bu\u{308}cher").unwrap(), "xn--bcher-kva");
+    assert_eq!(idna::to_ascii("faß").unwrap(), "xn--fa-hia");
+
+    // RFC 5892 中 DISALLOWED 的码点: 符号、组合标记开头
+    assert!(idna::to_ascii("☃").is_err());
+    assert!(idna::to_ascii("\u{301}a中").is_err());
+    assert!(idna::to_ascii("中\u{200B}国").is_err());
+    assert_eq!(idna::to_unicode("xn--n3h"), None);
+
+    // 上下文规则
+    assert!(idna::to_ascii("col·lecció").is_ok());
+    assert!(idna::to_ascii("co·llecció").is_err());
+    assert!(idna::to_ascii("ア・イ").is_ok());
+    assert!(idna::to_ascii("ä・b").is_err());
+    assert!(idna::to_ascii("\u{915}\u{94D}\u{200D}").is_ok());
+    assert!(idna::to_ascii("\u{915}\u{200D}").is_err());
+    assert!(idna::to_ascii("\u{661}\u{6F1}").is_err());
+}
+
+#[test]
+fn chinese_input_method_forms_are_mapped() {
+    // 表意句号和全角句号作为分隔符
+    let expected: DnsName = "例子.中国".parse().unwrap();
+    for input in ["例子。中国", "例子．中国", "例子｡中国", "例子。中国。"] {
+        let name: DnsName = input.parse().unwrap();
+        assert!(name.eq_exact(&expected), "{}", input);
+    }
+
+    // 全角字母映射成普通宽度, 再转成小写
+    let name: DnsName = "ｅｘａｍｐｌｅ.中国".parse().unwrap();
+    assert_eq!(name.to_string(), "example.xn--fiqs8s");
+    assert_eq!(idna::to_ascii("ＷＷＷ").unwrap(), "www");
+    assert_eq!(idna::to_ascii("ｅｘａｍｐｌｅ中").unwrap(), idna::to_ascii("example中").unwrap());
+    // 半角片假名映射成全角
+    assert_eq!(idna::to_ascii("ｱｲ").unwrap(), idna::to_ascii("アイ").unwrap());
+}
+
+#[test]
+fn unicode_names_are_written_as_a_labels() {
+    let name: DnsName = "www.例子.中国.".parse().unwrap();
+    assert_eq!(name.to_string(), "www.xn--fsqu00a.xn--fiqs8s");
+    assert_eq!(name.to_unicode(), "www.例子.中国");
+    assert_eq!(name, "WWW.xn--fsqu00a.XN--fiqs8s".parse().unwrap());
+    assert_eq!(format!("{:?}", name), "\"www.xn--fsqu00a.xn--fiqs8s\" (www.例子.中国)");
+    assert_eq!(name.unicode_hint().unwrap(), "www.例子.中国");
+    assert_eq!("www.example.com".parse::<DnsName>().unwrap().unicode_hint(), None);
+
+    let mut packet = DnsPacket::new();
+    packet.questions.push(DnsQuestion::new(name.clone(), QueryType::A));
+    let mut buffer = BytePacketBuffer::new();
+    packet.write(&mut buffer).unwrap();
+    assert!(buffer.buf[..buffer.pos].is_ascii());
+    let parsed = DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&buffer.buf[..buffer.pos])).unwrap();
+    assert!(parsed.questions[0].name.eq_exact(&name));
+
+    // 转义的字节不做 IDNA 转换
+    let raw: DnsName = "\\228\\184\\173.cn".parse().unwrap();
+    assert_eq!(raw.labels()[0], "中".as_bytes());
+}
@@ -0,0 +1,442 @@
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::net::{TcpListener, UdpSocket};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use smart_dns::cache::CacheKey;
+use smart_dns::config::{Config, Upstream};
+use smart_dns::context::ServerContext;
+use smart_dns::core_dns::*;
+use smart_dns::error::DnsError;
+
+// 在本地启动一个只应答一次的假上游, `respond` 根据请求生成要发回的字节,
+// 返回它的地址和收到的问题
+fn spawn_upstream(respond: impl FnOnce(&DnsPacket) -> Vec<u8> + Send + 'static) -> (Upstream, thread::JoinHandle<DnsQuestion>) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut upstream = Upstream::new(socket.local_addr().unwrap());
+    upstream.timeout = Duration::from_millis(300);
+    upstream.retries = 0;
+    upstream.randomize_case = true;
+
+    let handle = thread::spawn(move || {
+        let (mut req_buffer, src) = recv_packet(&socket).unwrap();
+        let request = DnsPacket::from_buffer(&mut req_buffer).unwrap();
+        socket.send_to(&respond(&request), src).unwrap();
+        request.questions[0].clone()
+    });
+
+    (upstream, handle)
+}
+
+// 假上游回答一条A记录, `echo` 决定如何回显问题中的域名
+fn fake_upstream(echo: fn(&DnsName) -> DnsName) -> (Upstream, thread::JoinHandle<DnsQuestion>) {
+    spawn_upstream(move |request| answer(request, echo))
+}
+
+fn answer(request: &DnsPacket, echo: fn(&DnsName) -> DnsName) -> Vec<u8> {
+    let question = &request.questions[0];
+    let mut reply = DnsPacket::new();
+    reply.header.id = request.header.id;
+    reply.header.response = true;
+    let name = echo(&question.name);
+    reply.questions.push(DnsQuestion::new(name.clone(), question.qtype));
+    reply.answers.push(DnsRecord::A {
+        domain: name,
+        class: QueryClass::IN,
+        addr: "192.0.2.1".parse().unwrap(),
+        ttl: 60,
+    });
+    let mut res_buffer = BytePacketBuffer::new();
+    reply.write(&mut res_buffer).unwrap();
+    res_buffer.buf[..res_buffer.pos].to_vec()
+}
+
+fn question() -> DnsQuestion {
+    DnsQuestion::new("WWW.Example-Domain-For-Case-Tests.com".parse().unwrap(), QueryType::A)
+}
+
+#[test]
+fn query_name_case_is_preserved_but_compared_insensitively() {
+    let mut packet = DnsPacket::new();
+    packet.questions.push(question());
+    let mut buffer = BytePacketBuffer::new();
+    packet.write(&mut buffer).unwrap();
+    let parsed = DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&buffer.buf[..buffer.pos])).unwrap();
+
+    let name = &parsed.questions[0].name;
+    assert_eq!(name.to_string(), "WWW.Example-Domain-For-Case-Tests.com");
+    assert_eq!(*name, "www.example-domain-for-case-tests.com".parse().unwrap());
+    assert!(!name.eq_exact(&name.to_lowercase()));
+}
+
+#[test]
+fn randomized_case_is_verified_and_restored() {
+    let (upstream, handle) = fake_upstream(|name| name.clone());
+    let result = lookup(&question(), &upstream, &mut QueryBudget::new(Duration::from_secs(2), 1), false, false).unwrap();
+
+    // 上游看到的是随机化后的大小写, 客户端拿到的是原来的
+    let seen = handle.join().unwrap();
+    assert_eq!(seen.name, question().name);
+    assert!(!seen.name.eq_exact(&question().name));
+    assert!(result.questions[0].name.eq_exact(&question().name));
+    assert!(result.answers[0].domain().eq_exact(&question().name));
+}
+
+#[test]
+fn reply_with_wrong_case_is_rejected() {
+    let (upstream, handle) = fake_upstream(|name| name.to_lowercase());
+    let result = lookup(&question(), &upstream, &mut QueryBudget::new(Duration::from_secs(2), 1), false, false);
+
+    handle.join().unwrap();
+    assert!(matches!(result, Err(DnsError::Timeout)));
+}
+
+#[test]
+fn truncated_reply_is_rejected() {
+    // 应答在最后一条记录的中间被截断, 且没有设置TC位
+    let (upstream, handle) = spawn_upstream(|request| {
+        let mut data = answer(request, |name| name.clone());
+        data.truncate(data.len() - 2);
+        data
+    });
+    let result = lookup(&question(), &upstream, &mut QueryBudget::new(Duration::from_secs(2), 1), false, false);
+
+    handle.join().unwrap();
+    assert!(matches!(result, Err(DnsError::BufferOverrun)));
+}
+
+#[test]
+fn truncated_query_gets_formerr() {
+    let server = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+
+    let mut request = DnsPacket::new();
+    request.header.id = 0x1234;
+    request.questions.push(question());
+    let mut buffer = BytePacketBuffer::new();
+    request.write(&mut buffer).unwrap();
+    // 问题部分在域名中间被截断
+    client.send_to(&buffer.buf[..20], server.local_addr().unwrap()).unwrap();
+
+    let (req_buffer, src) = recv_packet(&server).unwrap();
+    let context = Arc::new(ServerContext::new(Config::default()));
+    handle_query(context, server, src, req_buffer).unwrap();
+
+    let (mut res_buffer, _) = recv_packet(&client).unwrap();
+    let reply = DnsPacket::from_buffer(&mut res_buffer).unwrap();
+    assert_eq!(reply.header.id, 0x1234);
+    assert_eq!(reply.header.rescode, ResultCode::FORMERR);
+}
+
+#[test]
+fn extended_rcode_becomes_servfail_without_edns() {
+    let (upstream, handle) = spawn_upstream(|request| {
+        let mut reply = DnsPacket::new();
+        reply.header.id = request.header.id;
+        reply.header.response = true;
+        reply.header.rescode = ResultCode::BADCOOKIE;
+        reply.questions = request.questions.clone();
+        reply.edns = Some(Edns::new(EDNS_PAYLOAD_SIZE as u16));
+        let mut res_buffer = BytePacketBuffer::new();
+        reply.write(&mut res_buffer).unwrap();
+        res_buffer.buf[..res_buffer.pos].to_vec()
+    });
+    let context = ServerContext::new(Config {
+        upstreams: vec![upstream],
+        ..Config::default()
+    });
+
+    let mut request = DnsPacket::new();
+    request.questions.push(question());
+    let mut reply = resolve_query(&context, "127.0.0.1:5353".parse().unwrap(), request).unwrap();
+    handle.join().unwrap();
+
+    // 扩展 RCODE 只能放在 OPT 里, 客户端没有发送 OPT 时只能回答 SERVFAIL
+    assert_eq!(reply.header.rescode, ResultCode::SERVFAIL);
+    assert!(reply.write(&mut BytePacketBuffer::new()).is_ok());
+}
+
+#[test]
+fn client_do_bit_is_forwarded() {
+    for dnssec_ok in [false, true] {
+        let (upstream, handle) = spawn_upstream(move |request| {
+            // 上游看到的DO位与客户端一致
+            assert_eq!(request.edns.as_ref().unwrap().dnssec_ok, dnssec_ok);
+            answer(request, |name| name.clone())
+        });
+        let context = ServerContext::new(Config {
+            upstreams: vec![upstream],
+            ..Config::default()
+        });
+
+        let mut request = DnsPacket::new();
+        request.questions.push(question());
+        let mut edns = Edns::new(EDNS_PAYLOAD_SIZE as u16);
+        edns.dnssec_ok = dnssec_ok;
+        request.edns = Some(edns);
+        let reply = resolve_query(&context, "127.0.0.1:5353".parse().unwrap(), request).unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(reply.header.rescode, ResultCode::NOERROR);
+        assert_eq!(reply.edns.unwrap().dnssec_ok, dnssec_ok);
+    }
+}
+
+#[test]
+fn dnssec_header_bits_are_forwarded() {
+    for (dnssec_ok, checking_disabled) in [(false, false), (true, false), (true, true)] {
+        let (upstream, handle) = spawn_upstream(move |request| {
+            assert_eq!(request.header.checking_disabled, checking_disabled);
+            // 上游验证通过, 设置 AD 位
+            let mut data = answer(request, |name| name.clone());
+            data[3] |= 0x20;
+            data
+        });
+        let context = ServerContext::new(Config {
+            upstreams: vec![upstream],
+            ..Config::default()
+        });
+
+        let mut request = DnsPacket::new();
+        request.header.checking_disabled = checking_disabled;
+        request.questions.push(question());
+        let mut edns = Edns::new(EDNS_PAYLOAD_SIZE as u16);
+        edns.dnssec_ok = dnssec_ok;
+        request.edns = Some(edns);
+        let reply = resolve_query(&context, "127.0.0.1:5353".parse().unwrap(), request).unwrap();
+        handle.join().unwrap();
+
+        // AD 位只告诉请求了 DNSSEC 的客户端, CD 位原样回显
+        assert_eq!(reply.header.authed_data, dnssec_ok);
+        assert_eq!(reply.header.checking_disabled, checking_disabled);
+        // 没有验证过的应答不进入缓存
+        assert_eq!(context.cache.is_empty(), checking_disabled);
+        if !checking_disabled {
+            let key = CacheKey::new(&question().name, QueryType::A, QueryClass::IN);
+            assert!(context.cache.lookup(&key, dnssec_ok).unwrap().header.authed_data);
+        }
+    }
+}
+
+#[test]
+fn truncated_udp_reply_is_retried_over_tcp() {
+    // 假上游在同一个端口上监听UDP和TCP: UDP只返回设置了TC位的空应答, 完整的应答走TCP
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let listener = TcpListener::bind(socket.local_addr().unwrap()).unwrap();
+    let mut upstream = Upstream::new(socket.local_addr().unwrap());
+    upstream.timeout = Duration::from_millis(500);
+    upstream.randomize_case = true;
+
+    let handle = thread::spawn(move || {
+        let (mut req_buffer, src) = recv_packet(&socket).unwrap();
+        let request = DnsPacket::from_buffer(&mut req_buffer).unwrap();
+        let mut reply = DnsPacket::new();
+        reply.header.id = request.header.id;
+        reply.header.response = true;
+        reply.header.truncated_message = true;
+        reply.questions = request.questions.clone();
+        let mut res_buffer = BytePacketBuffer::new();
+        reply.write(&mut res_buffer).unwrap();
+        socket.send_to(&res_buffer.buf[..res_buffer.pos], src).unwrap();
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut len = [0u8; 2];
+        stream.read_exact(&mut len).unwrap();
+        let mut data = vec![0; u16::from_be_bytes(len) as usize];
+        stream.read_exact(&mut data).unwrap();
+        let request = DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&data)).unwrap();
+        let data = answer(&request, |name| name.clone());
+        stream.write_all(&(data.len() as u16).to_be_bytes()).unwrap();
+        stream.write_all(&data).unwrap();
+    });
+
+    let result = lookup(&question(), &upstream, &mut QueryBudget::new(Duration::from_secs(2), 1), false, false).unwrap();
+    handle.join().unwrap();
+
+    assert!(!result.header.truncated_message);
+    assert_eq!(result.answers.len(), 1);
+    assert!(result.answers[0].domain().eq_exact(&question().name));
+}
+
+#[test]
+fn reply_with_wrong_id_is_ignored() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut upstream = Upstream::new(socket.local_addr().unwrap());
+    upstream.timeout = Duration::from_millis(500);
+    upstream.retries = 0;
+
+    let handle = thread::spawn(move || {
+        let (mut req_buffer, src) = recv_packet(&socket).unwrap();
+        let request = DnsPacket::from_buffer(&mut req_buffer).unwrap();
+        // 先发一个事务ID不对的应答, 再发正确的
+        let mut forged = request.clone();
+        forged.header.id = request.header.id.wrapping_add(1);
+        socket.send_to(&answer(&forged, |name| name.clone()), src).unwrap();
+        socket.send_to(&answer(&request, |name| name.clone()), src).unwrap();
+        request.header.id
+    });
+
+    let result = lookup(&question(), &upstream, &mut QueryBudget::new(Duration::from_secs(2), 1), false, false).unwrap();
+    assert_eq!(result.header.id, handle.join().unwrap());
+    assert_eq!(result.answers.len(), 1);
+}
+
+#[test]
+fn reply_from_other_address_is_ignored() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut upstream = Upstream::new(socket.local_addr().unwrap());
+    upstream.timeout = Duration::from_millis(300);
+    upstream.retries = 0;
+
+    // 内容完全正确, 但是从另一个端口发出
+    let handle = thread::spawn(move || {
+        let (mut req_buffer, src) = recv_packet(&socket).unwrap();
+        let request = DnsPacket::from_buffer(&mut req_buffer).unwrap();
+        let other = UdpSocket::bind("127.0.0.1:0").unwrap();
+        other.send_to(&answer(&request, |name| name.clone()), src).unwrap();
+    });
+
+    let result = lookup(&question(), &upstream, &mut QueryBudget::new(Duration::from_secs(2), 1), false, false);
+    handle.join().unwrap();
+    assert!(matches!(result, Err(DnsError::Timeout)));
+}
+
+#[test]
+fn queries_use_random_ids_and_ports() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let upstream = Upstream::new(socket.local_addr().unwrap());
+
+    let handle = thread::spawn(move || {
+        let mut seen = Vec::new();
+        for _ in 0..5 {
+            let (mut req_buffer, src) = recv_packet(&socket).unwrap();
+            let request = DnsPacket::from_buffer(&mut req_buffer).unwrap();
+            socket.send_to(&answer(&request, |name| name.clone()), src).unwrap();
+            seen.push((request.header.id, src.port()));
+        }
+        seen
+    });
+    for _ in 0..5 {
+        lookup(&question(), &upstream, &mut QueryBudget::new(Duration::from_secs(2), 1), false, false).unwrap();
+    }
+    let seen = handle.join().unwrap();
+
+    // 每次查询都重新生成事务ID并绑定新的源端口
+    let ids: HashSet<u16> = seen.iter().map(|&(id, _)| id).collect();
+    let ports: HashSet<u16> = seen.iter().map(|&(_, port)| port).collect();
+    assert!(ids.len() > 1);
+    assert!(ports.len() > 1);
+}
+
+// 收到查询但从不应答的上游, 返回的套接字用来检查它收到了多少次查询
+fn silent_upstream(timeout: Duration, retries: u32) -> (Upstream, UdpSocket) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+    let mut upstream = Upstream::new(socket.local_addr().unwrap());
+    upstream.timeout = timeout;
+    upstream.retries = retries;
+    (upstream, socket)
+}
+
+fn received(socket: &UdpSocket) -> usize {
+    let mut count = 0;
+    while recv_packet(socket).is_ok() {
+        count += 1;
+    }
+    count
+}
+
+fn forward_context(upstreams: Vec<Upstream>, retry_budget: u32, deadline: Duration) -> ServerContext {
+    ServerContext::new(Config {
+        upstreams,
+        retry_budget,
+        deadline,
+        ..Config::default()
+    })
+}
+
+#[test]
+fn silent_upstream_fails_over_to_the_next() {
+    let (silent, socket) = silent_upstream(Duration::from_millis(200), 1);
+    let (working, handle) = fake_upstream(|name| name.clone());
+    let context = forward_context(vec![silent, working], 4, Duration::from_secs(2));
+
+    let result = forward(&context, &question(), false, false).unwrap();
+    handle.join().unwrap();
+    assert_eq!(result.answers.len(), 1);
+    // 第一个上游先重试一次才放弃
+    assert_eq!(received(&socket), 2);
+}
+
+#[test]
+fn timed_out_query_is_retried_on_the_same_upstream() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut upstream = Upstream::new(socket.local_addr().unwrap());
+    upstream.timeout = Duration::from_millis(200);
+    upstream.retries = 2;
+
+    // 前两次查询不应答, 重试时的事务ID与第一次相同
+    let handle = thread::spawn(move || {
+        let mut ids = Vec::new();
+        loop {
+            let (mut req_buffer, src) = recv_packet(&socket).unwrap();
+            let request = DnsPacket::from_buffer(&mut req_buffer).unwrap();
+            ids.push(request.header.id);
+            if ids.len() == 3 {
+                socket.send_to(&answer(&request, |name| name.clone()), src).unwrap();
+                return ids;
+            }
+        }
+    });
+    let context = forward_context(vec![upstream], 4, Duration::from_secs(2));
+
+    let result = forward(&context, &question(), false, false).unwrap();
+    let ids = handle.join().unwrap();
+    assert_eq!(result.answers.len(), 1);
+    assert!(ids.iter().all(|&id| id == ids[0]));
+}
+
+#[test]
+fn servfail_fails_over_to_the_next_upstream() {
+    let (failing, first) = spawn_upstream(|request| {
+        let mut reply = DnsPacket::new();
+        reply.header.id = request.header.id;
+        reply.header.response = true;
+        reply.header.rescode = ResultCode::SERVFAIL;
+        reply.questions = request.questions.clone();
+        let mut res_buffer = BytePacketBuffer::new();
+        reply.write(&mut res_buffer).unwrap();
+        res_buffer.buf[..res_buffer.pos].to_vec()
+    });
+    let (working, second) = fake_upstream(|name| name.clone());
+    let context = forward_context(vec![failing, working], 4, Duration::from_secs(2));
+
+    let result = forward(&context, &question(), false, false).unwrap();
+    first.join().unwrap();
+    second.join().unwrap();
+    assert_eq!(result.header.rescode, ResultCode::NOERROR);
+    assert_eq!(result.answers.len(), 1);
+}
+
+#[test]
+fn budget_limits_attempts_and_time() {
+    // 两次尝试都用在第一个上游上, 第二个上游收不到查询
+    let (first, first_socket) = silent_upstream(Duration::from_millis(100), 5);
+    let (second, second_socket) = silent_upstream(Duration::from_millis(100), 5);
+    let context = forward_context(vec![first, second], 2, Duration::from_secs(5));
+    let result = forward(&context, &question(), false, false);
+    assert!(matches!(result, Err(DnsError::Timeout)));
+    assert_eq!(received(&first_socket), 2);
+    assert_eq!(received(&second_socket), 0);
+
+    // 总时限比单次超时短时, 到时限就放弃
+    let (upstream, _socket) = silent_upstream(Duration::from_secs(2), 5);
+    let context = forward_context(vec![upstream], 10, Duration::from_millis(300));
+    let start = Instant::now();
+    let result = forward(&context, &question(), false, false);
+    assert!(matches!(result, Err(DnsError::Timeout)));
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
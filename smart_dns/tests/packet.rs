@@ -0,0 +1,153 @@
+use smart_dns::core_dns::*;
+use smart_dns::error::DnsError;
+
+fn a_records(count: usize) -> Vec<DnsRecord> {
+    (0..count)
+        .map(|i| DnsRecord::A {
+            domain: format!("host{}.example.com", i).parse().unwrap(),
+            class: QueryClass::IN,
+            addr: "192.0.2.1".parse().unwrap(),
+            ttl: 300,
+        })
+        .collect()
+}
+
+fn parse(buffer: &BytePacketBuffer) -> DnsPacket {
+    DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&buffer.buf[..buffer.pos])).unwrap()
+}
+
+#[test]
+fn buffer_grows_past_512_bytes() {
+    let mut packet = DnsPacket::new();
+    packet.header.response = true;
+    packet.answers = a_records(60);
+
+    // 经典的512字节缓冲区放不下
+    let err = packet.write(&mut BytePacketBuffer::new()).unwrap_err();
+    assert!(matches!(err, DnsError::BufferOverrun));
+
+    let mut buffer = BytePacketBuffer::growable(MAX_PACKET_SIZE);
+    packet.write(&mut buffer).unwrap();
+    assert!(buffer.pos > UDP_PACKET_SIZE);
+    assert_eq!(parse(&buffer).answers, packet.answers);
+
+    // 不能超过缓冲区的上限
+    assert!(packet.write(&mut BytePacketBuffer::growable(600)).is_err());
+    assert_eq!(BytePacketBuffer::growable(100_000).limit(), MAX_PACKET_SIZE);
+}
+
+#[test]
+fn packet_of_exactly_512_bytes() {
+    // 12字节报头 + 13字节域名 + 10字节类型/类别/TTL/长度 + 477字节 rdata
+    let mut packet = DnsPacket::new();
+    packet.header.response = true;
+    packet.answers.push(DnsRecord::TXT {
+        domain: "example.com".parse().unwrap(),
+        class: QueryClass::IN,
+        data: vec![vec![b'a'; 255], vec![b'b'; 220]],
+        ttl: 300,
+    });
+
+    let mut buffer = BytePacketBuffer::new();
+    packet.write(&mut buffer).unwrap();
+    assert_eq!(buffer.pos, UDP_PACKET_SIZE);
+    assert_eq!(parse(&buffer).answers, packet.answers);
+
+    // 少一个字节就读不完
+    let mut short = BytePacketBuffer::from_bytes(&buffer.buf[..UDP_PACKET_SIZE - 1]);
+    assert!(DnsPacket::from_buffer(&mut short).is_err());
+}
+
+#[test]
+fn repeated_suffixes_are_compressed() {
+    let mut packet = DnsPacket::new();
+    packet.header.response = true;
+    packet.questions.push(DnsQuestion::new("example.com".parse().unwrap(), QueryType::MX));
+    packet.answers.push(DnsRecord::MX {
+        domain: "example.com".parse().unwrap(),
+        class: QueryClass::IN,
+        priority: 10,
+        host: "mail.example.com".parse().unwrap(),
+        ttl: 300,
+    });
+
+    let mut compressed = BytePacketBuffer::new();
+    packet.write(&mut compressed).unwrap();
+    let mut plain = BytePacketBuffer::new();
+    plain.set_compression(false);
+    packet.write(&mut plain).unwrap();
+
+    // 问题中的 example.com 从偏移12开始: 回答的域名整个换成指针, mail.example.com 只写出 mail 标签
+    let data = &compressed.buf[..compressed.pos];
+    let question_end = 12 + 13 + 4;
+    assert_eq!(data[question_end..question_end + 2], [0xC0, 12]);
+    assert!(data.ends_with(&[4, b'm', b'a', b'i', b'l', 0xC0, 12]));
+    // 两处各省下11字节
+    assert_eq!(compressed.pos, plain.pos - 11 - 11);
+
+    assert_eq!(parse(&compressed).answers, packet.answers);
+    assert_eq!(parse(&plain).answers, packet.answers);
+}
+
+#[test]
+fn edns_round_trip() {
+    let mut packet = DnsPacket::new();
+    packet.header.response = true;
+    packet.header.rescode = ResultCode::BADVERS;
+    packet.questions.push(DnsQuestion::new("example.com".parse().unwrap(), QueryType::A));
+    let mut edns = Edns::new(4096);
+    edns.dnssec_ok = true;
+    edns.options.push(EdnsOption { code: 10, data: vec![1, 2, 3, 4, 5, 6, 7, 8] });
+    packet.edns = Some(edns);
+
+    let mut buffer = BytePacketBuffer::new();
+    packet.write(&mut buffer).unwrap();
+    let parsed = parse(&buffer);
+    // OPT 不计入附加记录, RCODE 的高8位从 OPT 中还原
+    assert!(parsed.resources.is_empty());
+    assert_eq!(parsed.header.rescode, ResultCode::BADVERS);
+    let edns = parsed.edns.unwrap();
+    assert_eq!(edns.udp_payload_size, 4096);
+    assert_eq!(edns.version, 0);
+    assert!(edns.dnssec_ok);
+    assert_eq!(edns.options, packet.edns.unwrap().options);
+}
+
+#[test]
+fn udp_size_follows_edns() {
+    let mut packet = DnsPacket::new();
+    assert_eq!(packet.max_udp_size(), UDP_PACKET_SIZE);
+
+    // 宣告值小于512时按512处理, 大于我们的上限时按上限处理
+    for (advertised, expected) in [(0, UDP_PACKET_SIZE), (1000, 1000), (65535, EDNS_PAYLOAD_SIZE)] {
+        packet.edns = Some(Edns::new(advertised));
+        assert_eq!(packet.max_udp_size(), expected);
+    }
+}
+
+#[test]
+fn compression_keeps_the_case_of_each_name() {
+    let mut packet = DnsPacket::new();
+    packet.header.response = true;
+    packet.questions.push(DnsQuestion::new("www.example.com".parse().unwrap(), QueryType::A));
+    packet.answers.push(DnsRecord::A {
+        domain: "WWW.EXAMPLE.COM".parse().unwrap(),
+        class: QueryClass::IN,
+        addr: "192.0.2.1".parse().unwrap(),
+        ttl: 300,
+    });
+    packet.answers.push(DnsRecord::A {
+        domain: "www.example.com".parse().unwrap(),
+        class: QueryClass::IN,
+        addr: "192.0.2.2".parse().unwrap(),
+        ttl: 300,
+    });
+
+    let mut buffer = BytePacketBuffer::new();
+    packet.write(&mut buffer).unwrap();
+    let parsed = parse(&buffer);
+    // 只有大小写完全相同的名称才会被压缩成指针
+    assert!(parsed.answers[0].domain().eq_exact(&"WWW.EXAMPLE.COM".parse().unwrap()));
+    assert!(parsed.answers[1].domain().eq_exact(&"www.example.com".parse().unwrap()));
+    assert_eq!(buffer.pos, 12 + 17 + 4 + (17 + 14) + (2 + 14));
+}
@@ -0,0 +1,729 @@
+use smart_dns::config::Config;
+use smart_dns::context::ServerContext;
+use smart_dns::core_dns::*;
+use smart_dns::error::DnsError;
+
+// 把记录放进应答包写出去, 再从字节中解析回来
+fn round_trip(records: Vec<DnsRecord>) -> DnsPacket {
+    let mut packet = DnsPacket::new();
+    packet.header.response = true;
+    packet.answers = records;
+
+    let mut buffer = BytePacketBuffer::new();
+    packet.write(&mut buffer).unwrap();
+
+    let mut buffer = BytePacketBuffer::from_bytes(&buffer.buf[..buffer.pos]);
+    DnsPacket::from_buffer(&mut buffer).unwrap()
+}
+
+#[test]
+fn txt_round_trip() {
+    let rec = DnsRecord::TXT {
+        domain: "example.com".parse().unwrap(),
+        class: QueryClass::IN,
+        data: vec![
+            b"v=spf1 include:_spf.example.com ~all".to_vec(),
+            vec![],
+            vec![0xFF; 255],
+        ],
+        ttl: 300,
+    };
+
+    assert_eq!(round_trip(vec![rec.clone()]).answers, vec![rec]);
+}
+
+#[test]
+fn txt_rejects_long_strings() {
+    let mut packet = DnsPacket::new();
+    packet.answers.push(DnsRecord::TXT {
+        domain: "example.com".parse().unwrap(),
+        class: QueryClass::IN,
+        data: vec![vec![b'a'; 256]],
+        ttl: 300,
+    });
+
+    let mut buffer = BytePacketBuffer::growable(MAX_PACKET_SIZE);
+    assert!(packet.write(&mut buffer).is_err());
+}
+
+#[test]
+fn ptr_round_trip() {
+    let rec = DnsRecord::PTR {
+        domain: "4.3.2.1.in-addr.arpa".parse().unwrap(),
+        class: QueryClass::IN,
+        host: "host.example.com".parse().unwrap(),
+        ttl: 3600,
+    };
+
+    assert_eq!(round_trip(vec![rec.clone()]).answers, vec![rec]);
+}
+
+#[test]
+fn srv_round_trip() {
+    let records = vec![
+        DnsRecord::SRV {
+            domain: "_sip._tcp.example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            priority: 10,
+            weight: 60,
+            port: 5060,
+            host: "sip1.example.com".parse().unwrap(),
+            ttl: 60,
+        },
+        DnsRecord::SRV {
+            domain: "_sip._tcp.example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            priority: 20,
+            weight: 0,
+            port: 5061,
+            host: "sip2.example.com".parse().unwrap(),
+            ttl: 60,
+        },
+    ];
+
+    assert_eq!(round_trip(records.clone()).answers, records);
+}
+
+#[test]
+fn srv_target_is_not_compressed() {
+    let mut packet = DnsPacket::new();
+    packet.answers.push(DnsRecord::SRV {
+        domain: "_sip._tcp.example.com".parse().unwrap(),
+        class: QueryClass::IN,
+        priority: 10,
+        weight: 60,
+        port: 5060,
+        host: "example.com".parse().unwrap(),
+        ttl: 60,
+    });
+
+    let mut buffer = BytePacketBuffer::new();
+    packet.write(&mut buffer).unwrap();
+
+    // 目标域名必须以完整的标签出现在报文末尾
+    let target = b"\x07example\x03com\x00";
+    assert!(buffer.buf[..buffer.pos].ends_with(target));
+}
+
+#[test]
+fn soa_round_trip() {
+    let rec = DnsRecord::SOA {
+        domain: "example.com".parse().unwrap(),
+        class: QueryClass::IN,
+        mname: "ns1.example.com".parse().unwrap(),
+        rname: "hostmaster.example.com".parse().unwrap(),
+        serial: 2024010101,
+        refresh: 7200,
+        retry: 3600,
+        expire: 1209600,
+        minimum: 300,
+        ttl: 3600,
+    };
+    let mut packet = DnsPacket::new();
+    packet.header.response = true;
+    packet.questions.push(DnsQuestion::new("example.com".parse().unwrap(), QueryType::SOA));
+    packet.answers.push(rec.clone());
+
+    let mut buffer = BytePacketBuffer::new();
+    packet.write(&mut buffer).unwrap();
+
+    // mname 和 rname 都只写出第一个标签, 后面指向问题中的 example.com (偏移12),
+    // 之后依次是 serial、refresh、retry、expire、minimum
+    let rdata_start = 12 + 13 + 4 + 2 + 10;
+    let mut expected = vec![3, b'n', b's', b'1', 0xC0, 12, 10];
+    expected.extend_from_slice(b"hostmaster");
+    expected.extend_from_slice(&[0xC0, 12]);
+    for value in [2024010101u32, 7200, 3600, 1209600, 300] {
+        expected.extend_from_slice(&value.to_be_bytes());
+    }
+    assert_eq!(buffer.buf[rdata_start - 2..rdata_start], (expected.len() as u16).to_be_bytes());
+    assert_eq!(buffer.buf[rdata_start..buffer.pos], expected[..]);
+
+    let parsed = DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&buffer.buf[..buffer.pos])).unwrap();
+    assert_eq!(parsed.answers, vec![rec]);
+}
+
+#[test]
+fn unknown_round_trip() {
+    // 私有类型 TYPE65280, RDATA 中看起来像域名指针的字节也必须原样保留
+    let rec = DnsRecord::UNKNOWN {
+        domain: "example.com".parse().unwrap(),
+        class: QueryClass::IN,
+        qtype: 65280,
+        data: vec![0xC0, 0x0C, 0x00, 0xFF, 0x01],
+        ttl: 300,
+    };
+
+    assert_eq!(round_trip(vec![rec.clone()]).answers, vec![rec]);
+}
+
+#[test]
+fn https_round_trip() {
+    let rec = DnsRecord::HTTPS {
+        domain: "example.com".parse().unwrap(),
+        class: QueryClass::IN,
+        priority: 1,
+        target: "".parse().unwrap(),
+        params: vec![
+            SvcParam::Mandatory(vec![1, 3]),
+            SvcParam::Alpn(vec![b"h2".to_vec(), b"h3".to_vec()]),
+            SvcParam::Port(8443),
+            SvcParam::Ipv4Hint(vec!["192.0.2.1".parse().unwrap()]),
+            SvcParam::Ech(vec![0x00, 0x45, 0xfe, 0x0d]),
+            SvcParam::Ipv6Hint(vec!["2001:db8::1".parse().unwrap()]),
+        ],
+        ttl: 300,
+    };
+
+    assert_eq!(round_trip(vec![rec.clone()]).answers, vec![rec]);
+}
+
+#[test]
+fn svcb_params_display() {
+    let params = [
+        SvcParam::Mandatory(vec![1, 4]),
+        SvcParam::Alpn(vec![b"h2".to_vec(), b"a,b".to_vec(), vec![0xFF]]),
+        SvcParam::NoDefaultAlpn,
+        SvcParam::Ipv4Hint(vec!["192.0.2.1".parse().unwrap(), "192.0.2.2".parse().unwrap()]),
+        SvcParam::Ech(b"hello".to_vec()),
+        SvcParam::Unknown(667, b"a\"b\x01".to_vec()),
+    ];
+    let shown: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+
+    assert_eq!(
+        shown,
+        vec![
+            "mandatory=alpn,ipv4hint",
+            "alpn=h2,a\\,b,\\255",
+            "no-default-alpn",
+            "ipv4hint=192.0.2.1,192.0.2.2",
+            "ech=aGVsbG8=",
+            "key667=\"a\\\"b\\001\"",
+        ]
+    );
+}
+
+// 用 UNKNOWN 写出手工构造的 HTTPS rdata (优先级1, 目标为根), 再按 HTTPS 解析回来
+fn parse_https(params: &[u8]) -> Result<DnsPacket, DnsError> {
+    let mut packet = DnsPacket::new();
+    packet.header.response = true;
+    packet.answers.push(DnsRecord::UNKNOWN {
+        domain: "example.com".parse().unwrap(),
+        class: QueryClass::IN,
+        qtype: QueryType::HTTPS.to_num(),
+        data: [&[0, 1, 0][..], params].concat(),
+        ttl: 300,
+    });
+    let mut buffer = BytePacketBuffer::new();
+    packet.write(&mut buffer).unwrap();
+
+    DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&buffer.buf[..buffer.pos]))
+}
+
+#[test]
+fn svcb_params_must_be_valid() {
+    // 不合法的参数不会让整个报文解析失败, 记录原样保留为 UNKNOWN
+    for params in [
+        // port 在 alpn 之前
+        &[0, 3, 0, 2, 0x01, 0xBB, 0, 1, 0, 3, 2, b'h', b'2'][..],
+        // 重复的 port
+        &[0, 3, 0, 2, 0x01, 0xBB, 0, 3, 0, 2, 0x01, 0xBB],
+        // 空的协议名
+        &[0, 1, 0, 4, 2, b'h', b'2', 0],
+        // 参数长度超出了 RDATA
+        &[0, 3, 0, 4, 0x01, 0xBB],
+    ] {
+        let packet = parse_https(params).unwrap();
+        match packet.answers[0] {
+            DnsRecord::UNKNOWN { qtype, ref data, .. } => {
+                assert_eq!(qtype, QueryType::HTTPS.to_num());
+                assert_eq!(*data, [&[0, 1, 0][..], params].concat());
+            }
+            ref other => panic!("unexpected record: {:?}", other),
+        }
+        assert_eq!(round_trip(packet.answers.clone()).answers, packet.answers);
+    }
+
+    // 非UTF-8的协议名按字节保存, 写出和表示形式都能还原
+    let packet = parse_https(&[0, 1, 0, 3, 2, 0xFF, 0xFE, 0, 3, 0, 2, 0x01, 0xBB]).unwrap();
+    let rec = &packet.answers[0];
+    match *rec {
+        DnsRecord::HTTPS { ref params, .. } => {
+            assert_eq!(*params, vec![SvcParam::Alpn(vec![vec![0xFF, 0xFE]]), SvcParam::Port(443)]);
+        }
+        ref other => panic!("unexpected record: {:?}", other),
+    }
+    assert_eq!(round_trip(vec![rec.clone()]).answers, vec![rec.clone()]);
+    assert_eq!(rec.to_string().parse::<DnsRecord>().unwrap(), *rec);
+
+    // 写出时也不允许重复的 key
+    let mut packet = DnsPacket::new();
+    packet.answers.push(DnsRecord::SVCB {
+        domain: "example.com".parse().unwrap(),
+        class: QueryClass::IN,
+        priority: 1,
+        target: "svc.example.com".parse().unwrap(),
+        params: vec![SvcParam::Port(443), SvcParam::Port(8443)],
+        ttl: 300,
+    });
+    assert!(packet.write(&mut BytePacketBuffer::new()).is_err());
+}
+
+#[test]
+fn caa_tlsa_sshfp_naptr_round_trip() {
+    let records = vec![
+        DnsRecord::CAA {
+            domain: "example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            flags: 128,
+            tag: b"issue".to_vec(),
+            value: b"letsencrypt.org".to_vec(),
+            ttl: 300,
+        },
+        DnsRecord::TLSA {
+            domain: "_443._tcp.example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            usage: 3,
+            selector: 1,
+            matching_type: 1,
+            data: vec![0xAB; 32],
+            ttl: 300,
+        },
+        DnsRecord::SSHFP {
+            domain: "host.example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            algorithm: 4,
+            fp_type: 2,
+            fingerprint: vec![0x12; 32],
+            ttl: 300,
+        },
+        DnsRecord::NAPTR {
+            domain: "example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            order: 100,
+            preference: 10,
+            flags: b"S".to_vec(),
+            services: b"SIP+D2U".to_vec(),
+            regexp: vec![],
+            replacement: "_sip._udp.example.com".parse().unwrap(),
+            ttl: 300,
+        },
+    ];
+
+    assert_eq!(round_trip(records.clone()).answers, records);
+}
+
+#[test]
+fn character_strings_keep_raw_bytes() {
+    let records = vec![
+        DnsRecord::NAPTR {
+            domain: "example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            order: 100,
+            preference: 10,
+            flags: vec![b'U', 0xFF],
+            services: vec![0xE4, 0xB8, 0xAD, 0x80],
+            regexp: b"!^.*$!sip:\xC3\x28@example.com!".to_vec(),
+            replacement: DnsName::root(),
+            ttl: 300,
+        },
+        DnsRecord::CAA {
+            domain: "example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            flags: 0,
+            tag: b"issuewild".to_vec(),
+            value: vec![0x80, 0xFE, b';'],
+            ttl: 300,
+        },
+    ];
+
+    assert_eq!(round_trip(records.clone()).answers, records);
+    for rec in &records {
+        assert_eq!(rec.to_string().parse::<DnsRecord>().unwrap(), *rec);
+    }
+
+    // tag 只能是1到15个字母和数字
+    for tag in [&b""[..], b"issue-wild", b"\xFFissue", b"abcdefghijklmnop"] {
+        let mut packet = DnsPacket::new();
+        packet.answers.push(DnsRecord::CAA {
+            domain: "example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            flags: 0,
+            tag: tag.to_vec(),
+            value: vec![],
+            ttl: 300,
+        });
+        assert!(packet.write(&mut BytePacketBuffer::new()).is_err());
+    }
+    assert!("example.com. 300 IN CAA 0 is_sue \"ca.example\"".parse::<DnsRecord>().is_err());
+}
+
+#[test]
+fn invalid_caa_tag_is_kept_opaque() {
+    // 上游应答中 tag 不合法的 CAA 记录原样转发, 不影响同一应答中的其他记录
+    let data = [&[0, 10][..], b"issue-wild", b"ca.example"].concat();
+    let records = vec![
+        DnsRecord::UNKNOWN {
+            domain: "example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            qtype: QueryType::CAA.to_num(),
+            data,
+            ttl: 300,
+        },
+        DnsRecord::A {
+            domain: "example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            addr: "192.0.2.1".parse().unwrap(),
+            ttl: 300,
+        },
+    ];
+
+    assert_eq!(round_trip(records.clone()).answers, records);
+}
+
+fn dnssec_records() -> Vec<DnsRecord> {
+    vec![
+        DnsRecord::DNSKEY {
+            domain: "example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            flags: 257,
+            protocol: 3,
+            algorithm: 13,
+            public_key: vec![0x5A; 64],
+            ttl: 3600,
+        },
+        DnsRecord::DS {
+            domain: "example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            key_tag: 2371,
+            algorithm: 13,
+            digest_type: 2,
+            digest: vec![0x01; 32],
+            ttl: 3600,
+        },
+        DnsRecord::RRSIG {
+            domain: "example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            type_covered: 1,
+            algorithm: 13,
+            labels: 2,
+            original_ttl: 3600,
+            expiration: 1_700_000_000,
+            inception: 1_690_000_000,
+            key_tag: 2371,
+            signer_name: "example.com".parse().unwrap(),
+            signature: vec![0x7F; 64],
+            ttl: 3600,
+        },
+        DnsRecord::NSEC {
+            domain: "a.example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            next_domain: "c.example.com".parse().unwrap(),
+            types: vec![1, 15, 28, 46, 47, 257],
+            ttl: 3600,
+        },
+        DnsRecord::NSEC3 {
+            domain: "2t7b4g4vsa5smi47k61mv5bv1a22bojr.example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            hash_algorithm: 1,
+            flags: 1,
+            iterations: 0,
+            salt: vec![],
+            next_hashed: vec![0x33; 20],
+            types: vec![2, 6, 46, 48, 51],
+            ttl: 3600,
+        },
+        DnsRecord::NSEC3PARAM {
+            domain: "example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            hash_algorithm: 1,
+            flags: 0,
+            iterations: 10,
+            salt: vec![0xAA, 0xBB],
+            ttl: 0,
+        },
+    ]
+}
+
+#[test]
+fn dnssec_round_trip() {
+    let records = dnssec_records();
+
+    assert_eq!(round_trip(records.clone()).answers, records);
+}
+
+// 用 UNKNOWN 写出手工构造的 NSEC rdata (下一个域名为 b.example), 再解析回来
+fn parse_nsec(bitmap: &[u8]) -> DnsRecord {
+    let data = [&b"\x01b\x07example\x00"[..], bitmap].concat();
+    let packet = round_trip(vec![DnsRecord::UNKNOWN {
+        domain: "a.example".parse().unwrap(),
+        class: QueryClass::IN,
+        qtype: QueryType::NSEC.to_num(),
+        data,
+        ttl: 300,
+    }]);
+    packet.answers[0].clone()
+}
+
+#[test]
+fn non_canonical_type_bitmaps_are_kept_verbatim() {
+    // 规范形式的位图解析成类型列表: A(1) 和 MX(15)
+    match parse_nsec(&[0, 2, 0x40, 0x01]) {
+        DnsRecord::NSEC { ref types, .. } => assert_eq!(*types, vec![1, 15]),
+        ref other => panic!("unexpected record: {:?}", other),
+    }
+
+    // 末尾多余的0字节、窗口乱序: 重新编码会改变 RDATA, 让签名失效, 只能原样保留
+    for bitmap in [&[0, 2, 0x40, 0x00][..], &[1, 1, 0x80, 0, 1, 0x40]] {
+        match parse_nsec(bitmap) {
+            DnsRecord::UNKNOWN { qtype, ref data, .. } => {
+                assert_eq!(qtype, QueryType::NSEC.to_num());
+                assert!(data.ends_with(bitmap));
+            }
+            ref other => panic!("unexpected record: {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn nsec_with_unordered_windows_round_trips() {
+    // 模糊测试发现的输入: NSEC 的类型位图先写窗口1 (类型256) 再写窗口0 (类型1),
+    // 曾被解析成 [256, 1], 写出时重新排序, 再次解析得到 [1, 256]
+    let mut data = vec![0, 0, 0x80, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+    data.extend_from_slice(b"\x01a\x07example\x00");
+    data.extend_from_slice(&[0, 47, 0, 1, 0, 0, 1, 0x2C, 0, 17]);
+    data.extend_from_slice(b"\x01b\x07example\x00");
+    data.extend_from_slice(&[1, 1, 0x80, 0, 1, 0x40]);
+
+    let mut packet = DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&data)).unwrap();
+    let mut buffer = BytePacketBuffer::growable(MAX_PACKET_SIZE);
+    packet.write(&mut buffer).unwrap();
+    let parsed = DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&buffer.buf[..buffer.pos])).unwrap();
+    assert_eq!(parsed.answers, packet.answers);
+    assert_eq!(buffer.buf[..buffer.pos], data[..]);
+}
+
+#[test]
+fn oversized_rdata_is_rejected() {
+    // RDATA 超过 65535 字节时写出失败, 不能溢出 RDLENGTH
+    let big = vec![0xAB; 65535];
+    let records = vec![
+        DnsRecord::DS {
+            domain: "example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            key_tag: 2371,
+            algorithm: 13,
+            digest_type: 2,
+            digest: big.clone(),
+            ttl: 300,
+        },
+        DnsRecord::DNSKEY {
+            domain: "example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            flags: 257,
+            protocol: 3,
+            algorithm: 13,
+            public_key: big.clone(),
+            ttl: 300,
+        },
+        DnsRecord::SSHFP {
+            domain: "example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            algorithm: 4,
+            fp_type: 2,
+            fingerprint: big.clone(),
+            ttl: 300,
+        },
+        DnsRecord::TLSA {
+            domain: "_443._tcp.example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            usage: 3,
+            selector: 1,
+            matching_type: 1,
+            data: big,
+            ttl: 300,
+        },
+    ];
+    for rec in records {
+        let mut packet = DnsPacket::new();
+        packet.answers.push(rec);
+        assert!(packet.write(&mut BytePacketBuffer::growable(MAX_PACKET_SIZE)).is_err());
+    }
+}
+
+#[test]
+fn strip_dnssec_keeps_requested_types() {
+    let mut packet = DnsPacket::new();
+    packet.answers = dnssec_records();
+    packet.strip_dnssec(QueryType::DNSKEY);
+
+    let kept: Vec<u16> = packet
+        .answers
+        .iter()
+        .map(|rec| match *rec {
+            DnsRecord::DNSKEY { .. } => 48,
+            DnsRecord::DS { .. } => 43,
+            DnsRecord::NSEC3PARAM { .. } => 51,
+            _ => 0,
+        })
+        .collect();
+    assert_eq!(kept, vec![48, 43, 51]);
+
+    let mut packet = DnsPacket::new();
+    packet.answers = dnssec_records();
+    packet.strip_dnssec(QueryType::RRSIG);
+    assert!(packet.answers.iter().any(|rec| matches!(rec, DnsRecord::RRSIG { .. })));
+}
+
+#[test]
+fn class_round_trip() {
+    let mut packet = DnsPacket::new();
+    packet.questions.push(DnsQuestion::with_class(
+        "version.bind".parse().unwrap(),
+        QueryType::TXT,
+        QueryClass::CH,
+    ));
+    packet.answers.push(DnsRecord::TXT {
+        domain: "version.bind".parse().unwrap(),
+        class: QueryClass::CH,
+        data: vec![b"smart_dns".to_vec()],
+        ttl: 0,
+    });
+    // RFC 2136 中从 RRset 删除单条记录的更新使用 NONE 类别
+    packet.authorities.push(DnsRecord::A {
+        domain: "www.example.com".parse().unwrap(),
+        class: QueryClass::NONE,
+        addr: "192.0.2.1".parse().unwrap(),
+        ttl: 0,
+    });
+
+    let mut buffer = BytePacketBuffer::new();
+    packet.write(&mut buffer).unwrap();
+    let parsed = DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&buffer.buf[..buffer.pos])).unwrap();
+
+    assert_eq!(parsed.questions, packet.questions);
+    assert_eq!(parsed.answers, packet.answers);
+    assert_eq!(parsed.authorities, packet.authorities);
+}
+
+#[test]
+fn malformed_packets_report_typed_errors() {
+    // 报头声明有一个问题, 但后面没有数据
+    let truncated = [0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0];
+    match DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&truncated)) {
+        Err(DnsError::BufferOverrun) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // 问题的域名是指向自己的压缩指针
+    let mut looping = truncated.to_vec();
+    looping.extend_from_slice(&[0xC0, 12, 0, 1, 0, 1]);
+    let err = DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&looping)).unwrap_err();
+    assert!(matches!(err, DnsError::PointerLoop));
+    assert!(err.is_format_error());
+}
+
+#[test]
+fn rdata_length_must_match() {
+    let mut packet = DnsPacket::new();
+    packet.header.response = true;
+    packet.answers.push(DnsRecord::A {
+        domain: "example.com".parse().unwrap(),
+        class: QueryClass::IN,
+        addr: "192.0.2.1".parse().unwrap(),
+        ttl: 60,
+    });
+    let mut buffer = BytePacketBuffer::new();
+    packet.write(&mut buffer).unwrap();
+
+    // 把 RDLENGTH 从4改成5, 并在末尾补一个字节
+    let mut data = buffer.buf[..buffer.pos].to_vec();
+    let len_pos = data.len() - 6;
+    data[len_pos + 1] = 5;
+    data.push(0);
+    let err = DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&data)).unwrap_err();
+    assert!(matches!(err, DnsError::BadRdataLength(1)));
+}
+
+#[test]
+fn dns_name_validation() {
+    let name: DnsName = "www.example.com.".parse().unwrap();
+    assert_eq!(name, "www.example.com".parse().unwrap());
+    assert_eq!(name.labels().len(), 3);
+    assert_eq!(name.to_string(), "www.example.com");
+    assert!(DnsName::root().is_root());
+    assert_eq!(".".parse::<DnsName>().unwrap(), DnsName::root());
+
+    let label = "a".repeat(63);
+    assert!(format!("{}.com", label).parse::<DnsName>().is_ok());
+    assert!(matches!(format!("{}a.com", label).parse::<DnsName>(), Err(DnsError::LabelTooLong(64))));
+    assert!("www..example.com".parse::<DnsName>().is_err());
+    assert!("example.com..".parse::<DnsName>().is_err());
+
+    // 4个63字节的标签编码后为 4 * 64 + 1 = 257 字节
+    let long = [label.as_str(); 4].join(".");
+    assert!(matches!(long.parse::<DnsName>(), Err(DnsError::NameTooLong(257))));
+    let max = format!("{}.{}", [label.as_str(); 3].join("."), "a".repeat(61));
+    assert_eq!(max.parse::<DnsName>().unwrap().wire_len(), 255);
+}
+
+#[test]
+fn extended_rcode_round_trip() {
+    let mut packet = DnsPacket::new();
+    packet.header.response = true;
+    packet.header.opcode = Opcode::NOTIFY;
+    packet.header.rescode = ResultCode::BADCOOKIE;
+    packet.edns = Some(Edns::new(1232));
+
+    let mut buffer = BytePacketBuffer::new();
+    packet.write(&mut buffer).unwrap();
+    // 报头中只剩低4位: 23 & 0x0F = 7
+    assert_eq!(buffer.buf[3] & 0x0F, 7);
+    let parsed = DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&buffer.buf[..buffer.pos])).unwrap();
+    assert_eq!(parsed.header.opcode, Opcode::NOTIFY);
+    assert_eq!(parsed.header.rescode, ResultCode::BADCOOKIE);
+    assert_eq!(parsed.edns.unwrap().extended_rcode, 1);
+
+    // 没有 OPT 时无法表示
+    packet.edns = None;
+    assert!(packet.write(&mut BytePacketBuffer::new()).is_err());
+}
+
+#[test]
+fn unsupported_opcode_is_not_forwarded() {
+    let context = ServerContext::new(Config::default());
+    let mut request = DnsPacket::new();
+    request.header.id = 0x4242;
+    request.header.opcode = Opcode::UPDATE;
+    request.questions.push(DnsQuestion::new("example.com".parse().unwrap(), QueryType::SOA));
+
+    let reply = resolve_query(&context, "127.0.0.1:5353".parse().unwrap(), request).unwrap();
+    assert_eq!(reply.header.id, 0x4242);
+    assert_eq!(reply.header.opcode, Opcode::UPDATE);
+    assert_eq!(reply.header.rescode, ResultCode::NOTIMP);
+}
+
+#[test]
+fn escaped_names_round_trip() {
+    // 标签中含有点、反斜杠、空格和非ASCII字节
+    let labels = vec![b"a.b".to_vec(), b"c\\d e".to_vec(), vec![0x00, 0xFF, b'x'], b"example".to_vec()];
+    let name = DnsName::from_labels(labels.clone()).unwrap();
+    let text = name.to_string();
+    assert_eq!(text, "a\\.b.c\\\\d\\032e.\\000\\255x.example");
+
+    let parsed: DnsName = text.parse().unwrap();
+    assert!(parsed.eq_exact(&name));
+    assert!("a\\.b.c\\\\d\\032e.\\000\\255x.example.".parse::<DnsName>().unwrap().eq_exact(&name));
+    assert!("bad\\256.example".parse::<DnsName>().is_err());
+    assert!("trailing\\".parse::<DnsName>().is_err());
+
+    let rec = DnsRecord::CNAME {
+        domain: name.clone(),
+        class: QueryClass::IN,
+        host: "\\(weird\\).example".parse().unwrap(),
+        ttl: 60,
+    };
+    let parsed = round_trip(vec![rec.clone()]).answers;
+    assert_eq!(parsed, vec![rec]);
+    assert_eq!(parsed[0].domain().labels(), &labels[..]);
+}
@@ -0,0 +1,279 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use smart_dns::core_dns::*;
+
+// 不引入依赖的简单伪随机数生成器 (xorshift64), 固定种子保证失败可以复现
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn bytes(&mut self, max: u64) -> Vec<u8> {
+        let len = self.below(max + 1);
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+
+    // 至少一个字节, 用于表示形式中不能为空的十六进制/base64 字段
+    fn blob(&mut self, max: u64) -> Vec<u8> {
+        let len = 1 + self.below(max);
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+
+    // 大小写混合的字母、数字和连字符; 读出的域名必须保留原来的大小写
+    fn name(&mut self) -> DnsName {
+        const CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-";
+        // 从少量后缀里选, 只有大小写不同的后缀也会出现, 让压缩指针也能被用到
+        const SUFFIXES: [&str; 5] = ["example.com", "example.net", "com", "Example.COM", "EXAMPLE.com"];
+        let mut labels = Vec::new();
+        for _ in 0..self.below(3) {
+            let len = 1 + self.below(20);
+            labels.push((0..len).map(|_| CHARS[self.below(CHARS.len() as u64) as usize] as char).collect::<String>());
+        }
+        labels.push(SUFFIXES[self.below(SUFFIXES.len() as u64) as usize].to_string());
+        labels.join(".").parse().unwrap()
+    }
+
+    fn class(&mut self) -> QueryClass {
+        match self.below(8) {
+            0 => QueryClass::CH,
+            1 => QueryClass::HS,
+            2 => QueryClass::from_num(self.next() as u16),
+            _ => QueryClass::IN,
+        }
+    }
+
+    fn qtype(&mut self) -> QueryType {
+        const TYPES: [u16; 22] = [1, 2, 5, 6, 12, 15, 16, 28, 33, 35, 43, 44, 46, 47, 48, 50, 51, 52, 64, 65, 255, 257];
+        match self.below(4) {
+            0 => QueryType::from_num(self.next() as u16),
+            _ => QueryType::from_num(TYPES[self.below(TYPES.len() as u64) as usize]),
+        }
+    }
+
+    // 规范的类型列表: 升序且不重复
+    fn types(&mut self) -> Vec<u16> {
+        let mut types: Vec<u16> = (0..self.below(6)).map(|_| self.next() as u16 % 1024).collect();
+        types.sort_unstable();
+        types.dedup();
+        types
+    }
+
+    fn svc_params(&mut self) -> Vec<SvcParam> {
+        let mut params = Vec::new();
+        if self.below(2) == 0 {
+            params.push(SvcParam::Alpn((0..1 + self.below(3)).map(|_| self.blob(8)).collect()));
+        }
+        if self.below(2) == 0 {
+            params.push(SvcParam::Port(self.next() as u16));
+        }
+        if self.below(2) == 0 {
+            params.push(SvcParam::Ipv4Hint((0..1 + self.below(3)).map(|_| Ipv4Addr::from(self.next() as u32)).collect()));
+        }
+        if self.below(2) == 0 {
+            params.push(SvcParam::Ech(self.blob(32)));
+        }
+        if self.below(2) == 0 {
+            params.push(SvcParam::Ipv6Hint(vec![Ipv6Addr::from(((self.next() as u128) << 64) | self.next() as u128)]));
+        }
+        if self.below(2) == 0 {
+            params.push(SvcParam::Unknown(7 + self.below(1000) as u16, self.bytes(16)));
+        }
+        params
+    }
+
+    fn record(&mut self) -> DnsRecord {
+        let domain = self.name();
+        let class = self.class();
+        let ttl = self.next() as u32;
+        match self.below(24) {
+            0 => DnsRecord::A { domain, class, addr: Ipv4Addr::from(self.next() as u32), ttl },
+            1 => DnsRecord::AAAA { domain, class, addr: Ipv6Addr::from(((self.next() as u128) << 64) | self.next() as u128), ttl },
+            2 => DnsRecord::NS { domain, class, host: self.name(), ttl },
+            3 => DnsRecord::CNAME { domain, class, host: self.name(), ttl },
+            4 => DnsRecord::MX { domain, class, priority: self.next() as u16, host: self.name(), ttl },
+            5 => DnsRecord::TXT {
+                domain,
+                class,
+                data: (0..self.below(4)).map(|_| self.bytes(255)).collect(),
+                ttl,
+            },
+            6 => DnsRecord::SRV {
+                domain,
+                class,
+                priority: self.next() as u16,
+                weight: self.next() as u16,
+                port: self.next() as u16,
+                host: self.name(),
+                ttl,
+            },
+            7 => DnsRecord::SOA {
+                domain,
+                class,
+                mname: self.name(),
+                rname: self.name(),
+                serial: self.next() as u32,
+                refresh: self.next() as u32,
+                retry: self.next() as u32,
+                expire: self.next() as u32,
+                minimum: self.next() as u32,
+                ttl,
+            },
+            8 => DnsRecord::CAA { domain, class, flags: self.next() as u8, tag: b"issue".to_vec(), value: self.bytes(64), ttl },
+            9 => DnsRecord::PTR { domain, class, host: self.name(), ttl },
+            10 => DnsRecord::NAPTR {
+                domain,
+                class,
+                order: self.next() as u16,
+                preference: self.next() as u16,
+                flags: self.bytes(4),
+                services: self.bytes(16),
+                regexp: self.bytes(32),
+                replacement: self.name(),
+                ttl,
+            },
+            11 => DnsRecord::DS {
+                domain,
+                class,
+                key_tag: self.next() as u16,
+                algorithm: self.next() as u8,
+                digest_type: self.next() as u8,
+                digest: self.blob(48),
+                ttl,
+            },
+            12 => DnsRecord::SSHFP {
+                domain,
+                class,
+                algorithm: self.next() as u8,
+                fp_type: self.next() as u8,
+                fingerprint: self.blob(32),
+                ttl,
+            },
+            13 => DnsRecord::RRSIG {
+                domain,
+                class,
+                type_covered: self.next() as u16,
+                algorithm: self.next() as u8,
+                labels: self.next() as u8,
+                original_ttl: self.next() as u32,
+                expiration: self.next() as u32,
+                inception: self.next() as u32,
+                key_tag: self.next() as u16,
+                signer_name: self.name(),
+                signature: self.blob(96),
+                ttl,
+            },
+            14 => DnsRecord::NSEC { domain, class, next_domain: self.name(), types: self.types(), ttl },
+            15 => DnsRecord::DNSKEY {
+                domain,
+                class,
+                flags: self.next() as u16,
+                protocol: self.next() as u8,
+                algorithm: self.next() as u8,
+                public_key: self.blob(96),
+                ttl,
+            },
+            16 => DnsRecord::NSEC3 {
+                domain,
+                class,
+                hash_algorithm: self.next() as u8,
+                flags: self.next() as u8,
+                iterations: self.next() as u16,
+                salt: self.bytes(16),
+                next_hashed: self.blob(20),
+                types: self.types(),
+                ttl,
+            },
+            17 => DnsRecord::NSEC3PARAM {
+                domain,
+                class,
+                hash_algorithm: self.next() as u8,
+                flags: self.next() as u8,
+                iterations: self.next() as u16,
+                salt: self.bytes(16),
+                ttl,
+            },
+            18 => DnsRecord::TLSA {
+                domain,
+                class,
+                usage: self.next() as u8,
+                selector: self.next() as u8,
+                matching_type: self.next() as u8,
+                data: self.blob(64),
+                ttl,
+            },
+            19 => DnsRecord::SVCB { domain, class, priority: self.next() as u16, target: self.name(), params: self.svc_params(), ttl },
+            20 => DnsRecord::HTTPS { domain, class, priority: self.next() as u16, target: self.name(), params: self.svc_params(), ttl },
+            _ => DnsRecord::UNKNOWN { domain, class, qtype: 0xFF00 | self.next() as u16, data: self.bytes(64), ttl },
+        }
+    }
+}
+
+// 逐条比较, 域名的大小写也必须相同
+fn assert_exact(parsed: &[DnsRecord], expected: &[DnsRecord]) {
+    assert_eq!(parsed, expected);
+    for (a, b) in parsed.iter().zip(expected) {
+        assert!(a.eq_exact(b), "{} != {}", a, b);
+    }
+}
+
+// 随机生成的数据包写出后再解析, 必须得到相同的内容
+#[test]
+fn write_then_parse_is_identity() {
+    let mut rng = Rng(0x5EED_D15C);
+    for _ in 0..500 {
+        let mut packet = DnsPacket::new();
+        packet.header.id = rng.next() as u16;
+        packet.header.response = true;
+        packet.questions.push(DnsQuestion::with_class(rng.name(), rng.qtype(), rng.class()));
+        packet.answers = (0..rng.below(6)).map(|_| rng.record()).collect();
+        packet.authorities = (0..rng.below(3)).map(|_| rng.record()).collect();
+        packet.resources = (0..rng.below(3)).map(|_| rng.record()).collect();
+
+        let mut buffer = BytePacketBuffer::growable(MAX_PACKET_SIZE);
+        packet.write(&mut buffer).unwrap();
+        let parsed = DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&buffer.buf[..buffer.pos])).unwrap();
+
+        assert_eq!(parsed.header.id, packet.header.id);
+        assert_eq!(parsed.questions, packet.questions);
+        assert!(parsed.questions[0].name.eq_exact(&packet.questions[0].name));
+        assert_exact(&parsed.answers, &packet.answers);
+        assert_exact(&parsed.authorities, &packet.authorities);
+        assert_exact(&parsed.resources, &packet.resources);
+    }
+}
+
+// 把合法报文截断在任意位置, 解析必须返回错误而不是 panic
+#[test]
+fn truncated_packets_are_rejected() {
+    let mut rng = Rng(0x7E57);
+    let mut packet = DnsPacket::new();
+    packet.header.response = true;
+    packet.questions.push(DnsQuestion::new("www.example.com".parse().unwrap(), QueryType::A));
+    packet.answers = (0..8).map(|_| rng.record()).collect();
+
+    let mut buffer = BytePacketBuffer::growable(MAX_PACKET_SIZE);
+    packet.write(&mut buffer).unwrap();
+    for len in 0..buffer.pos {
+        assert!(DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&buffer.buf[..len])).is_err());
+    }
+}
+
+// 随机生成的记录输出成区域文件格式后再解析, 必须得到相同的记录
+#[test]
+fn display_then_parse_is_identity() {
+    let mut rng = Rng(0x2015_F11E);
+    for _ in 0..500 {
+        let rec = rng.record();
+        let text = rec.to_string();
+        assert_exact(&[text.parse::<DnsRecord>().unwrap()], &[rec]);
+    }
+}
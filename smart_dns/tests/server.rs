@@ -0,0 +1,223 @@
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, Shutdown, TcpListener, TcpStream, UdpSocket};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use smart_dns::cache::CacheKey;
+use smart_dns::config::Config;
+use smart_dns::context::ServerContext;
+use smart_dns::core_dns::*;
+use smart_dns::error::DnsError;
+
+// 预先把 `answers` 放进缓存, 查询不需要访问上游
+fn cached_context(name: &str, answers: Vec<DnsRecord>) -> Arc<ServerContext> {
+    cached_context_with(Config::default(), name, answers)
+}
+
+fn cached_context_with(config: Config, name: &str, answers: Vec<DnsRecord>) -> Arc<ServerContext> {
+    let context = ServerContext::new(config);
+    let mut result = DnsPacket::new();
+    result.answers = answers;
+    let key = CacheKey::new(&name.parse().unwrap(), QueryType::A, QueryClass::IN);
+    context.cache.store(key, &result, false);
+    Arc::new(context)
+}
+
+fn query(id: u16, name: &str) -> Vec<u8> {
+    let mut packet = DnsPacket::new();
+    packet.header.id = id;
+    packet.header.recursion_desired = true;
+    packet.questions.push(DnsQuestion::new(name.parse().unwrap(), QueryType::A));
+    let mut buffer = BytePacketBuffer::new();
+    packet.write(&mut buffer).unwrap();
+    buffer.buf[..buffer.pos].to_vec()
+}
+
+fn read_frame(stream: &mut TcpStream) -> DnsPacket {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len).unwrap();
+    let mut data = vec![0; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut data).unwrap();
+    DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&data)).unwrap()
+}
+
+#[test]
+fn tcp_queries_are_length_prefixed_and_pipelined() {
+    let context = cached_context(
+        "www.example.com",
+        vec![DnsRecord::A {
+            domain: "www.example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            addr: "192.0.2.1".parse().unwrap(),
+            ttl: 300,
+        }],
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        handle_tcp_connection(context, stream)
+    });
+
+    // 三个查询一次性写出, 最后一个只有报头, 声明的问题不存在
+    let mut messages = Vec::new();
+    for data in [query(1, "www.example.com"), query(2, "WWW.Example.COM"), query(3, "www.example.com")[..12].to_vec()] {
+        messages.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        messages.extend_from_slice(&data);
+    }
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(&messages).unwrap();
+
+    let first = read_frame(&mut stream);
+    assert_eq!(first.header.id, 1);
+    assert_eq!(first.answers.len(), 1);
+    let second = read_frame(&mut stream);
+    assert_eq!(second.header.id, 2);
+    assert!(second.questions[0].name.eq_exact(&"WWW.Example.COM".parse().unwrap()));
+    let third = read_frame(&mut stream);
+    assert_eq!(third.header.id, 3);
+    assert_eq!(third.header.rescode, ResultCode::FORMERR);
+
+    // 客户端关闭连接后服务端正常结束
+    stream.shutdown(Shutdown::Write).unwrap();
+    server.join().unwrap().unwrap();
+}
+
+#[test]
+fn oversized_udp_answers_are_truncated() {
+    let answers: Vec<DnsRecord> = (0..60)
+        .map(|i| DnsRecord::A {
+            domain: "many.example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            addr: Ipv4Addr::new(192, 0, 2, i),
+            ttl: 300,
+        })
+        .collect();
+    let context = cached_context("many.example.com", answers);
+    let server = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+
+    // 没有EDNS的客户端最多收到512字节, 放不下时设置TC位让它改用TCP
+    let data = query(7, "many.example.com");
+    client.send_to(&data, server.local_addr().unwrap()).unwrap();
+    let (req_buffer, src) = recv_packet(&server).unwrap();
+    handle_query(context.clone(), server.clone(), src, req_buffer).unwrap();
+
+    let (mut res_buffer, _) = recv_packet(&client).unwrap();
+    assert!(res_buffer.buf.len() <= UDP_PACKET_SIZE);
+    let reply = DnsPacket::from_buffer(&mut res_buffer).unwrap();
+    assert_eq!(reply.header.id, 7);
+    assert!(reply.header.truncated_message);
+    assert!(reply.answers.len() < 60);
+
+    // 通过EDNS宣告了更大的UDP缓冲区时可以完整返回
+    let mut packet = DnsPacket::new();
+    packet.header.id = 8;
+    packet.questions.push(DnsQuestion::new("many.example.com".parse().unwrap(), QueryType::A));
+    packet.edns = Some(Edns::new(EDNS_PAYLOAD_SIZE as u16));
+    let mut buffer = BytePacketBuffer::new();
+    packet.write(&mut buffer).unwrap();
+    client.send_to(&buffer.buf[..buffer.pos], server.local_addr().unwrap()).unwrap();
+    let (req_buffer, src) = recv_packet(&server).unwrap();
+    handle_query(context, server, src, req_buffer).unwrap();
+
+    let (mut res_buffer, _) = recv_packet(&client).unwrap();
+    let reply = DnsPacket::from_buffer(&mut res_buffer).unwrap();
+    assert!(!reply.header.truncated_message);
+    assert_eq!(reply.answers.len(), 60);
+}
+
+#[test]
+fn edns_is_echoed_and_versions_are_checked() {
+    let context = cached_context(
+        "www.example.com",
+        vec![DnsRecord::A {
+            domain: "www.example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            addr: "192.0.2.1".parse().unwrap(),
+            ttl: 300,
+        }],
+    );
+    let src = "127.0.0.1:5353".parse().unwrap();
+    let request = |version| {
+        let mut packet = DnsPacket::new();
+        packet.questions.push(DnsQuestion::new("www.example.com".parse().unwrap(), QueryType::A));
+        let mut edns = Edns::new(1232);
+        edns.version = version;
+        packet.edns = Some(edns);
+        packet
+    };
+
+    // 应答带上我们自己的OPT
+    let reply = resolve_query(&context, src, request(0)).unwrap();
+    assert_eq!(reply.header.rescode, ResultCode::NOERROR);
+    assert_eq!(reply.answers.len(), 1);
+    assert_eq!(reply.edns.unwrap().udp_payload_size, EDNS_PAYLOAD_SIZE as u16);
+
+    // 不支持的版本返回 BADVERS, 不回答问题
+    let mut reply = resolve_query(&context, src, request(1)).unwrap();
+    assert_eq!(reply.header.rescode, ResultCode::BADVERS);
+    assert!(reply.answers.is_empty());
+    assert_eq!(reply.edns.as_ref().unwrap().version, 0);
+    let mut buffer = BytePacketBuffer::new();
+    reply.write(&mut buffer).unwrap();
+    let parsed = DnsPacket::from_buffer(&mut BytePacketBuffer::from_bytes(&buffer.buf[..buffer.pos])).unwrap();
+    assert_eq!(parsed.header.rescode, ResultCode::BADVERS);
+}
+
+#[test]
+fn responses_are_dropped() {
+    let context = cached_context(
+        "www.example.com",
+        vec![DnsRecord::A {
+            domain: "www.example.com".parse().unwrap(),
+            class: QueryClass::IN,
+            addr: "192.0.2.1".parse().unwrap(),
+            ttl: 300,
+        }],
+    );
+    let server = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+
+    // 格式正确但 QR=1 的报文不能当作查询回答, 否则两个转发器会互相反射
+    let mut data = query(9, "www.example.com");
+    data[2] |= 0x80;
+    client.send_to(&data, server.local_addr().unwrap()).unwrap();
+    let (req_buffer, src) = recv_packet(&server).unwrap();
+    let result = handle_query(context, server, src, req_buffer);
+    assert!(matches!(result, Err(DnsError::UnexpectedResponse)));
+    assert!(recv_packet(&client).is_err());
+}
+
+#[test]
+fn compression_can_be_disabled_in_config() {
+    let answers = vec![DnsRecord::CNAME {
+        domain: "www.example.com".parse().unwrap(),
+        class: QueryClass::IN,
+        host: "web.example.com".parse().unwrap(),
+        ttl: 300,
+    }];
+    let server = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+
+    let mut sizes = Vec::new();
+    for compression in [true, false] {
+        let config = Config { compression, ..Config::default() };
+        let context = cached_context_with(config, "www.example.com", answers.clone());
+        client.send_to(&query(10, "www.example.com"), server.local_addr().unwrap()).unwrap();
+        let (req_buffer, src) = recv_packet(&server).unwrap();
+        handle_query(context, server.clone(), src, req_buffer).unwrap();
+
+        let (mut res_buffer, _) = recv_packet(&client).unwrap();
+        sizes.push(res_buffer.buf.len());
+        let reply = DnsPacket::from_buffer(&mut res_buffer).unwrap();
+        assert_eq!(reply.answers, answers);
+    }
+
+    // 关闭压缩后回答的所有者和 CNAME 目标都写出完整的标签
+    assert_eq!(sizes[1], sizes[0] + 15 + 11);
+}
@@ -0,0 +1,126 @@
+use smart_dns::core_dns::*;
+
+// 解析后再输出, 必须得到完全相同的文本
+fn assert_canonical(line: &str) {
+    let rec: DnsRecord = line.parse().unwrap_or_else(|e| panic!("{}: {}", line, e));
+    assert_eq!(rec.to_string(), line);
+}
+
+#[test]
+fn display_matches_master_file_syntax() {
+    let rec = DnsRecord::A {
+        domain: "www.example.com".parse().unwrap(),
+        class: QueryClass::IN,
+        addr: "1.2.3.4".parse().unwrap(),
+        ttl: 300,
+    };
+    assert_eq!(rec.to_string(), "www.example.com. 300 IN A 1.2.3.4");
+    assert_eq!("www.example.com 300 A 1.2.3.4".parse::<DnsRecord>().unwrap(), rec);
+    assert_eq!("www.example.com. IN 300 a 1.2.3.4 ; 注释".parse::<DnsRecord>().unwrap(), rec);
+
+    let question = DnsQuestion::with_class("version.bind".parse().unwrap(), QueryType::TXT, QueryClass::CH);
+    assert_eq!(question.to_string(), "version.bind. CH TXT");
+}
+
+#[test]
+fn every_type_round_trips() {
+    for line in &[
+        "example.com. 3600 IN NS ns1.example.com.",
+        "www.example.com. 60 IN CNAME example.com.",
+        "example.com. 3600 IN SOA ns1.example.com. hostmaster.example.com. 2024010101 7200 3600 1209600 300",
+        "4.3.2.1.in-addr.arpa. 300 IN PTR www.example.com.",
+        "example.com. 300 IN MX 10 mail.example.com.",
+        "example.com. 300 IN TXT \"v=spf1 -all\" \"say \\\"hi\\\" \\\\ \\255\"",
+        "example.com. 300 IN AAAA 2001:db8::1",
+        "_sip._udp.example.com. 300 IN SRV 10 60 5060 sip.example.com.",
+        "example.com. 300 IN NAPTR 100 10 \"S\" \"SIP+D2U\" \"\" _sip._udp.example.com.",
+        "example.com. 300 IN DS 12345 13 2 49FD46E6C4B45C55D4AC69CBD3CD34AC1AFE51DE",
+        "host.example.com. 300 IN SSHFP 4 2 123456789ABCDEF0",
+        "example.com. 300 IN RRSIG A 13 2 300 20240201000000 20240101000000 12345 example.com. dGVzdCBzaWduYXR1cmU=",
+        "example.com. 300 IN NSEC www.example.com. A NS SOA RRSIG NSEC DNSKEY TYPE65280",
+        "example.com. 300 IN DNSKEY 257 3 13 cHVibGljIGtleQ==",
+        "example.com. 300 IN NSEC3 1 1 0 - 2T7B4G4VSA5SMI47K61MV5BV1A22BOJR A RRSIG",
+        "example.com. 300 IN NSEC3PARAM 1 0 10 AABBCCDD",
+        "_443._tcp.example.com. 300 IN TLSA 3 1 1 0123456789ABCDEF",
+        "example.com. 300 IN HTTPS 1 . alpn=h2,h3 port=443 ipv4hint=192.0.2.1,192.0.2.2 ech=AQID",
+        "_dns.example.com. 300 IN SVCB 1 dns.example.com. mandatory=alpn alpn=dot,a\\,b no-default-alpn key65000=\"a\\032b\"",
+        "example.com. 300 IN SVCB 0 svc.example.com.",
+        "example.com. 300 IN CAA 0 issue \"letsencrypt.org\"",
+        "example.com. 300 CH TYPE65280 \\# 3 ABCDEF",
+        "example.com. 300 IN TYPE65281 \\# 0",
+        "a\\.b.example. 300 IN A 192.0.2.1",
+    ] {
+        assert_canonical(line);
+    }
+}
+
+#[test]
+fn parser_accepts_zone_file_variations() {
+    let multi_line = "example.com. 3600 IN SOA ns1.example.com. hostmaster.example.com. (
+                          2024010101 ; serial
+                          7200       ; refresh
+                          3600 1209600 300 )";
+    assert_eq!(
+        multi_line.parse::<DnsRecord>().unwrap().to_string(),
+        "example.com. 3600 IN SOA ns1.example.com. hostmaster.example.com. 2024010101 7200 3600 1209600 300"
+    );
+
+    // base64 数据可以分段书写, 时间可以直接写秒数
+    let rrsig: DnsRecord = "example.com. 300 IN RRSIG A 13 2 300 1706745600 1704067200 12345 example.com. dGVzdCBz aWduYXR1cmU="
+        .parse()
+        .unwrap();
+    assert!(rrsig.to_string().contains(" 20240201000000 20240101000000 "));
+
+    // 国际化域名转换成 A-label
+    let rec: DnsRecord = "例子.中国. 300 IN A 192.0.2.1".parse().unwrap();
+    assert_eq!(rec.to_string(), "xn--fsqu00a.xn--fiqs8s. 300 IN A 192.0.2.1");
+
+    for bad in &[
+        "example.com. IN A 1.2.3.4",
+        "example.com. 300 IN A 1.2.3",
+        "example.com. 300 IN A 1.2.3.4 extra",
+        "example.com. 300 IN BOGUS 1",
+        "example.com. 300 IN TXT \"unterminated",
+        "example.com. 300 IN TYPE65280 \\# 2 ABCDEF",
+        "example.com. 300 IN RRSIG A 13 2 300 20241301000000 20240101000000 1 example.com. AA==",
+    ] {
+        assert!(bad.parse::<DnsRecord>().is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn generic_format_for_any_type() {
+    // 应答部分中的 OPT 被当作未解析的记录保存, 必须写成 TYPE41 才能解析回来
+    let opt = DnsRecord::UNKNOWN {
+        domain: DnsName::root(),
+        class: QueryClass::UNKNOWN(1232),
+        qtype: 41,
+        data: vec![0, 10, 0, 2, 0xAB, 0xCD],
+        ttl: 0,
+    };
+    assert_eq!(opt.to_string(), ". 0 CLASS1232 TYPE41 \\# 6 000A0002ABCD");
+    assert_eq!(opt.to_string().parse::<DnsRecord>().unwrap(), opt);
+
+    // 已知类型的通用格式按线路格式解析成对应的记录
+    let raw_a = DnsRecord::UNKNOWN {
+        domain: "example.com".parse().unwrap(),
+        class: QueryClass::IN,
+        qtype: 1,
+        data: vec![192, 0, 2, 1],
+        ttl: 300,
+    };
+    assert_eq!(raw_a.to_string(), "example.com. 300 IN TYPE1 \\# 4 C0000201");
+    let a = DnsRecord::A {
+        domain: "example.com".parse().unwrap(),
+        class: QueryClass::IN,
+        addr: "192.0.2.1".parse().unwrap(),
+        ttl: 300,
+    };
+    assert_eq!(raw_a.to_string().parse::<DnsRecord>().unwrap(), a);
+    assert_eq!("example.com. 300 IN A \\# 4 C0000201".parse::<DnsRecord>().unwrap(), a);
+    assert_eq!(
+        "example.com. 300 IN MX \\# 5 000A016D00".parse::<DnsRecord>().unwrap().to_string(),
+        "example.com. 300 IN MX 10 m."
+    );
+    assert!("example.com. 300 IN A \\# 3 C00002".parse::<DnsRecord>().is_err());
+}